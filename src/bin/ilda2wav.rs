@@ -5,11 +5,24 @@ use clap::{App, Arg};
 use common::full_buf_writer::FullBufWriter;
 use common::memory_cycle::MemoryCycleIterator;
 use common::memory_cycle::MemoryCycleIteratorExt;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use hound::{Error as HoundError, WavSpec, WavWriter};
 use ilda::animation::{Animation, AnimationFrameIterator, Frame};
 use ilda::SimplePoint;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, BufWriter, Cursor, Error as IoError, Read, Seek, Stdin, Stdout, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug)]
+enum Error {
+    NoSuchOutputDevice(String),
+    NoDefaultOutputDevice,
+    FailedToOpenOutputStream(String),
+}
 
 trait SampleWrite {
     fn write(&mut self, samples: &Vec<f64>) -> Result<(), IoError>;
@@ -27,6 +40,61 @@ struct PcmWriter<T: Write> {
     bps: BytesPerSample,
 }
 
+// unifies the raw PCM transports (file, STDOUT, a networked projector over TCP) behind
+// a single Write impl, so PcmWriter's I8A/I16A/I32A encoding logic doesn't need a
+// separate struct per transport
+enum Writer {
+    File(BufWriter<File>),
+    Stdout(Stdout),
+    Tcp(TcpStream),
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::File(w) => w.write(buf),
+            Writer::Stdout(w) => w.write(buf),
+            Writer::Tcp(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::File(w) => w.flush(),
+            Writer::Stdout(w) => w.flush(),
+            Writer::Tcp(w) => w.flush(),
+        }
+    }
+}
+
+// XORs the encoded byte stream with a repeating key before it reaches the inner
+// writer, so the same bytes can be decoded on the receiving end with the same key
+struct XorWriter<W: Write> {
+    inner: W,
+    key: Vec<u8>,
+    pos: usize,
+}
+
+impl<W: Write> Write for XorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encoded: Vec<u8> = buf
+            .iter()
+            .map(|b| {
+                let k = b ^ self.key[self.pos % self.key.len()];
+                self.pos += 1;
+                k
+            })
+            .collect();
+
+        self.inner.write_all(&encoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 // consts for mapping -1.0 ~ 1.0 to min/max values of i8,i16,i32: y = ax + b
 const I8A: f64 = (i8::max_value() as f64 - i8::min_value() as f64) / 2.0;
 const I8B: f64 = (i8::max_value() as f64 + i8::min_value() as f64) / 2.0;
@@ -111,16 +179,281 @@ impl<W: Write + Seek> SampleWrite for HoundWriter<W> {
     }
 }
 
+// streams samples straight to a sound card via cpal instead of a file. The main loop's
+// writes feed a ring buffer; the audio callback drains it on its own thread, so
+// playback keeps running in real time regardless of how fast we produce samples.
+struct CpalSink {
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    _stream: cpal::Stream,
+}
+
+impl CpalSink {
+    fn new(
+        sample_rate: u32,
+        channels: usize,
+        device_name: &Option<String>,
+    ) -> Result<CpalSink, Error> {
+        let host = cpal::default_host();
+
+        let device = match device_name {
+            Some(name) => host
+                .output_devices()
+                .map_err(|e| Error::NoSuchOutputDevice(e.to_string()))?
+                .find(|d| d.name().map(|n| &n == name).unwrap_or(false))
+                .ok_or_else(|| Error::NoSuchOutputDevice(name.clone()))?,
+            None => host
+                .default_output_device()
+                .ok_or(Error::NoDefaultOutputDevice)?,
+        };
+
+        let config = cpal::StreamConfig {
+            channels: channels as u16,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let callback_buffer = buffer.clone();
+
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut buffer = callback_buffer.lock().unwrap();
+                    for sample in data.iter_mut() {
+                        *sample = buffer.pop_front().unwrap_or(0.0);
+                    }
+                },
+                |err| eprintln!("cpal output stream error: {}", err),
+            )
+            .map_err(|e| Error::FailedToOpenOutputStream(e.to_string()))?;
+
+        stream
+            .play()
+            .map_err(|e| Error::FailedToOpenOutputStream(e.to_string()))?;
+
+        Ok(CpalSink {
+            buffer,
+            _stream: stream,
+        })
+    }
+}
+
+impl SampleWrite for CpalSink {
+    fn write(&mut self, samples: &Vec<f64>) -> Result<(), IoError> {
+        let mut buffer = self.buffer.lock().unwrap();
+        for sample in samples {
+            buffer.push_back(*sample as f32);
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), IoError> {
+        // block until the callback has drained everything we queued, so the process
+        // doesn't exit while audio is still playing
+        while !self.buffer.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        Ok(())
+    }
+}
+
+const SMOOTHING_TAPS: usize = 16;
+
+// low-pass FIR built from a windowed-sinc kernel, modeling the finite bandwidth of a
+// galvanometer's mechanical response. Applied only to axis channels so color/blanking
+// (Step::Jump) stays crisp.
+struct SincSmoother {
+    kernel: Vec<f64>,
+    ring: VecDeque<f64>,
+}
+
+impl SincSmoother {
+    fn new(cutoff: f64) -> SincSmoother {
+        // `cutoff` is documented as a fraction of Nyquist (0-1); the kernel below
+        // wants fc as a fraction of the sample rate (0-0.5, where 0.5 = Nyquist)
+        let fc = cutoff / 2.0;
+        let half = (SMOOTHING_TAPS - 1) as f64 / 2.0;
+
+        let mut kernel: Vec<f64> = (0..SMOOTHING_TAPS)
+            .map(|i| {
+                let x = i as f64 - half;
+                let sinc = if x == 0.0 {
+                    2.0 * fc
+                } else {
+                    (2.0 * std::f64::consts::PI * fc * x).sin() / (std::f64::consts::PI * x)
+                };
+                let window = 0.5
+                    - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / (SMOOTHING_TAPS - 1) as f64).cos();
+                sinc * window
+            })
+            .collect();
+
+        let sum: f64 = kernel.iter().sum();
+        for tap in kernel.iter_mut() {
+            *tap /= sum;
+        }
+
+        SincSmoother {
+            kernel,
+            ring: VecDeque::from(vec![0.0; SMOOTHING_TAPS]),
+        }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.ring.pop_front();
+        self.ring.push_back(sample);
+
+        self.ring
+            .iter()
+            .zip(self.kernel.iter())
+            .map(|(s, k)| s * k)
+            .sum()
+    }
+}
+
+// applies the per-channel smoother (axis channels only) before handing samples off
+// to the configured SampleWrite sink. Returns the processed samples too, so a preview
+// rasterizer can draw exactly what the sink received.
+fn write_smoothed(
+    output: &mut dyn SampleWrite,
+    smoothers: &mut Vec<Option<SincSmoother>>,
+    samples: &Vec<f64>,
+) -> Result<Vec<f64>, IoError> {
+    let processed: Vec<f64> = samples
+        .iter()
+        .zip(smoothers.iter_mut())
+        .map(|(sample, smoother)| match smoother {
+            Some(smoother) => smoother.process(*sample),
+            None => *sample,
+        })
+        .collect();
+
+    output.write(&processed)?;
+
+    Ok(processed)
+}
+
+// renders the interpolated beam path to numbered PPM/P6 image files, so --max-velocity,
+// --dwell-gain and --smoothing can be sanity-checked visually instead of by ear
+struct PreviewRasterizer {
+    dir: String,
+    width: u32,
+    height: u32,
+    framebuffer: Vec<u8>,
+    x_channel: Option<usize>,
+    y_channel: Option<usize>,
+    r_channel: Option<usize>,
+    g_channel: Option<usize>,
+    b_channel: Option<usize>,
+    l_channel: Option<usize>,
+    last: Option<(f64, f64)>,
+    frame_index: u32,
+}
+
+impl PreviewRasterizer {
+    fn new(dir: String, size: (u32, u32), channels: &[MapConfiguration]) -> PreviewRasterizer {
+        let find = |targets: &[Mapper]| {
+            channels
+                .iter()
+                .position(|mc| targets.iter().any(|t| mc.mapper == *t))
+        };
+
+        let (width, height) = size;
+
+        PreviewRasterizer {
+            dir,
+            width,
+            height,
+            framebuffer: vec![0; width as usize * height as usize * 3],
+            x_channel: find(&[map_x, map_x_inv]),
+            y_channel: find(&[map_y, map_y_inv]),
+            r_channel: find(&[map_r]),
+            g_channel: find(&[map_g]),
+            b_channel: find(&[map_b]),
+            l_channel: find(&[map_l]),
+            last: None,
+            frame_index: 0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f64]) {
+        let x = self.x_channel.map(|i| samples[i]).unwrap_or(0.0);
+        let y = self.y_channel.map(|i| samples[i]).unwrap_or(0.0);
+        let is_blank = self.l_channel.map(|i| samples[i] < 0.0).unwrap_or(false);
+
+        let color = [
+            self.r_channel
+                .map(|i| (255.0 * (samples[i] + 1.0) / 2.0) as u8)
+                .unwrap_or(255),
+            self.g_channel
+                .map(|i| (255.0 * (samples[i] + 1.0) / 2.0) as u8)
+                .unwrap_or(255),
+            self.b_channel
+                .map(|i| (255.0 * (samples[i] + 1.0) / 2.0) as u8)
+                .unwrap_or(255),
+        ];
+
+        let px = (self.width as f64 / 2.0) * (x + 1.0);
+        let py = (self.height as f64 / 2.0) * (1.0 - y);
+
+        if let Some(last) = self.last {
+            if !is_blank {
+                self.draw_line(last, (px, py), color);
+            }
+        }
+
+        self.last = Some((px, py));
+    }
+
+    fn draw_line(&mut self, from: (f64, f64), to: (f64, f64), color: [u8; 3]) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).max(1.0) as u32;
+
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let x = (from.0 + (to.0 - from.0) * t).round();
+            let y = (from.1 + (to.1 - from.1) * t).round();
+
+            if x >= 0.0 && x < self.width as f64 && y >= 0.0 && y < self.height as f64 {
+                let offset = (y as usize * self.width as usize + x as usize) * 3;
+                self.framebuffer[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        let path = format!("{}/frame_{:05}.ppm", self.dir, self.frame_index);
+        let mut file = File::create(&path).expect("Failed to create preview frame file.");
+
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)
+            .expect("Failed to write PPM header.");
+        file.write_all(&self.framebuffer)
+            .expect("Failed to write PPM data.");
+
+        for byte in self.framebuffer.iter_mut() {
+            *byte = 0;
+        }
+        self.last = None;
+        self.frame_index += 1;
+    }
+}
+
 struct Options {
     input: Box<dyn Read>,
     output: Box<dyn SampleWrite>,
     repeat: bool,
-    mdpm: u32,
     fps: f64,
     pps: f64,
     sample_rate: u32,
     correctness: f64,
     channels: Vec<MapConfiguration>,
+    max_velocity: f64,
+    dwell_gain: f64,
+    dwell_angle_threshold: f64,
+    smoothing: Option<f64>,
+    preview: Option<String>,
+    preview_size: (u32, u32),
 }
 
 type Mapper = fn(&SimplePoint) -> f64;
@@ -135,11 +468,11 @@ enum Step {
     Jump(f64),
 }
 
-fn get_options<'a>() -> Options {
+fn get_options<'a>() -> Result<Options, Error> {
     let matches = App::new("ilda2gui")
         .version("0.1.0")
         .author("Lukas <lukasjapan@gmail.com>")
-        .about("Generates a wav file for an ILDA projector hooked to a sound card.")
+        .about("Generates a wav file for an ILDA projector hooked to a sound card. Use ildawav2ilda to invert this conversion.")
         .arg(
             Arg::with_name("PPS")
                 .short("p")
@@ -194,7 +527,7 @@ Any value above zero may slow down the animation."#)
             Arg::with_name("REPEAT")
                 .short("r")
                 .long("repeat")
-                .help("Repeats the input animation forever. Can only be used if outputting raw PCM samples to STDOUT."),
+                .help("Repeats the input animation forever. Can only be used if outputting raw PCM samples to STDOUT, or in live mode."),
         )
         .arg(
             Arg::with_name("RAW")
@@ -202,6 +535,18 @@ Any value above zero may slow down the animation."#)
                 .long("raw")
                 .help("Output raw PCM data. (Do not write wav header)"),
         )
+        .arg(
+            Arg::with_name("LIVE")
+                .short("L")
+                .long("live")
+                .help("Stream samples directly to a sound card via cpal instead of writing a file. Ignores FILES/RAW/BPS for output."),
+        )
+        .arg(
+            Arg::with_name("DEVICE")
+                .long("device")
+                .help("Name of the output device to use in live mode. Defaults to the system's default output device.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("CHANNELS")
                 .help(r#"A string that defines the output channel configuration. Use one or more of the following characters:
@@ -239,11 +584,56 @@ Warning: If writing to STDOUT, the output file will be buffered unless raw PCM s
                 .index(2),
         )
         .arg(
-            Arg::with_name("MDPS")
+            Arg::with_name("MAXVELOCITY")
                 .short("m")
-                .long("mdps")
-                .default_value("100")
-                .help("Meh - Need to think about how to implement this constraint. This should probably be related to the pps setting")
+                .long("max-velocity")
+                .default_value("4.0")
+                .help("Maximum galvo slew rate, in normalized (-1..1) axis units per second. Moves faster than this are slowed down (extra samples are inserted) instead of cutting corners.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("DWELLGAIN")
+                .long("dwell-gain")
+                .default_value("3.0")
+                .help("Gain k for corner dwell: the number of extra samples held at a vertex is round(k * angle / PI), where angle is the turn angle in radians between the incoming and outgoing segment.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("DWELLANGLE")
+                .long("dwell-angle")
+                .default_value("0.3")
+                .help("Turn angle in radians above which a corner dwell is inserted so the galvo can physically reach the vertex before changing direction.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("SMOOTHING")
+                .long("smoothing")
+                .help("Low-pass cutoff (as a fraction of the sample rate's Nyquist frequency, 0-1) applied to axis channels via a 16-tap windowed-sinc filter, modeling the galvo's mechanical response. Off by default.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("HOST")
+                .long("host")
+                .help("Stream raw PCM samples to host:port over TCP instead of writing a file. Overrides FILES/RAW for output.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ENCRYPT")
+                .long("encrypt")
+                .help("Key for a symmetric XOR keystream applied to the encoded byte stream before it is sent to --host. Ignored unless --host is given.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("PREVIEW")
+                .long("preview")
+                .help("Directory to write a numbered frame_NNNNN.ppm image per frame into, tracing the beam path alongside the usual audio output, which is still written. Useful for sanity-checking --max-velocity/--dwell-gain/--smoothing without a projector.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("SIZE")
+                .long("size")
+                .default_value("800x800")
+                .help("Width and height of preview images, as WxH. Ignored unless --preview is given.")
                 .takes_value(true),
         )
         .get_matches();
@@ -373,17 +763,75 @@ Warning: If writing to STDOUT, the output file will be buffered unless raw PCM s
 
     let repeat = matches.is_present("REPEAT");
 
-    let output: Box<SampleWrite> = if raw_pcm {
+    let live = matches.is_present("LIVE");
+
+    let device_name = matches.value_of("DEVICE").map(String::from);
+
+    let smoothing = match matches.value_of("SMOOTHING") {
+        Some(v) => Some(v.parse().expect("Invalid number.")),
+        None => None,
+    };
+
+    let host = matches.value_of("HOST").map(String::from);
+
+    let encrypt_key = matches.value_of("ENCRYPT").map(|key| {
+        if key.is_empty() {
+            panic!("Encryption key must not be empty.");
+        }
+        key.as_bytes().to_vec()
+    });
+
+    let preview = matches.value_of("PREVIEW").map(String::from);
+
+    if let Some(dir) = &preview {
+        std::fs::create_dir_all(dir).expect("Failed to create preview directory.");
+    }
+
+    let preview_size = {
+        let raw = matches.value_of("SIZE").unwrap();
+        let parts: Vec<&str> = raw.split('x').collect();
+
+        match parts.as_slice() {
+            [w, h] => (
+                w.parse().expect("Invalid number."),
+                h.parse().expect("Invalid number."),
+            ),
+            _ => panic!("Invalid size, expected WxH."),
+        }
+    };
+
+    let output: Box<SampleWrite> = if live {
+        Box::new(CpalSink::new(sample_rate, channels.len(), &device_name)?)
+    } else if let Some(host) = &host {
+        let writer = Writer::Tcp(TcpStream::connect(host).expect("Failed to connect to host."));
+
+        match encrypt_key {
+            Some(key) => Box::new(PcmWriter {
+                writer: XorWriter {
+                    inner: writer,
+                    key,
+                    pos: 0,
+                },
+                bps: bits_per_sample_enum,
+            }),
+            None => Box::new(PcmWriter {
+                writer,
+                bps: bits_per_sample_enum,
+            }),
+        }
+    } else if raw_pcm {
         match file_out {
             Some(filename) => {
-                let writer = BufWriter::new(File::create(filename).expect("Failed to open file."));
+                let writer = Writer::File(BufWriter::new(
+                    File::create(filename).expect("Failed to open file."),
+                ));
                 Box::new(PcmWriter {
                     writer,
                     bps: bits_per_sample_enum,
                 })
             }
             None => {
-                let writer = io::stdout();
+                let writer = Writer::Stdout(io::stdout());
                 Box::new(PcmWriter {
                     writer,
                     bps: bits_per_sample_enum,
@@ -417,19 +865,14 @@ Warning: If writing to STDOUT, the output file will be buffered unless raw PCM s
         }
     };
 
-    if repeat && !(file_out.is_none() && raw_pcm) {
-        panic!("Repeating input is only allowed when outputting raw PCM samples to STDOUT.")
+    if repeat && !live && !(file_out.is_none() && raw_pcm) {
+        panic!("Repeating input is only allowed when outputting raw PCM samples to STDOUT, or in live mode.")
     }
 
-    Options {
+    Ok(Options {
         input,
         output,
         repeat,
-        mdpm: matches
-            .value_of("MDPS")
-            .unwrap()
-            .parse()
-            .expect("Invalid number."),
         fps: matches
             .value_of("FPS")
             .unwrap()
@@ -447,7 +890,25 @@ Warning: If writing to STDOUT, the output file will be buffered unless raw PCM s
             .expect("Invalid number."),
         sample_rate,
         channels,
-    }
+        max_velocity: matches
+            .value_of("MAXVELOCITY")
+            .unwrap()
+            .parse()
+            .expect("Invalid number."),
+        dwell_gain: matches
+            .value_of("DWELLGAIN")
+            .unwrap()
+            .parse()
+            .expect("Invalid number."),
+        dwell_angle_threshold: matches
+            .value_of("DWELLANGLE")
+            .unwrap()
+            .parse()
+            .expect("Invalid number."),
+        smoothing,
+        preview,
+        preview_size,
+    })
 }
 
 fn map_x(point: &SimplePoint) -> f64 {
@@ -514,25 +975,49 @@ impl WavProgress {
         //        );
         n
     }
+
+    // account for samples written outside of advance()'s time budget (slew-rate cap,
+    // corner dwell), so cur_sample keeps matching the real number of samples written
+    // and later advance() calls don't drift out of sync with --fps/--pps
+    fn force_samples(&mut self, n: u64) {
+        self.cur_sample += n;
+    }
 }
 
 // struct that holds mapped points of a frame and the total traveled distance
-// TODO: add angle info?
 #[derive(Debug)]
 struct FramePoints {
     pos: Vec<f64>,
     dist: f64,
 }
 
-fn main() {
-    let mut options = get_options();
-
-    let max_dist_per_frame = options.mdpm as f64 / options.fps as f64;
+fn main() -> Result<(), Error> {
+    let mut options = get_options()?;
 
     let time_per_frame = 1.0 / options.fps as f64;
     let time_per_sample = 1.0 / options.sample_rate as f64;
     let time_per_point = 1.0 / options.pps;
 
+    // max normalized axis distance a single sample is allowed to cover
+    let max_step = options.max_velocity * time_per_sample;
+
+    let mut smoothers: Vec<Option<SincSmoother>> = options
+        .channels
+        .iter()
+        .map(|mc| {
+            if mc.is_axis {
+                options.smoothing.map(SincSmoother::new)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut rasterizer = options
+        .preview
+        .as_ref()
+        .map(|dir| PreviewRasterizer::new(dir.clone(), options.preview_size, &options.channels));
+
     let mut cur_progress = WavProgress {
         cur_time: 0.0,
         cur_sample: 0,
@@ -593,13 +1078,25 @@ fn main() {
             points.len()
         );
 
-        for point in points {
+        for idx in 0..points.len() {
+            let point = &points[idx];
             // println!("{:?}", point);
             // moving to this point can use this amount of time of the shared_time
             let share_of_frame = point.dist / total_dist;
             let n = cur_progress.advance(shared_time * share_of_frame);
 
-            // TODO: check max speed and adjust
+            // slew-rate cap: don't let a move cut corners faster than the galvo can follow
+            let min_n = if max_step > 0.0 {
+                (point.dist / max_step).ceil() as u64
+            } else {
+                0
+            };
+            let n = if min_n > n {
+                cur_progress.force_samples(min_n - n);
+                min_n
+            } else {
+                n
+            };
 
             if n > 0 {
                 // instruction for samples
@@ -629,19 +1126,75 @@ fn main() {
                         })
                         .collect();
 
-                    options.output.write(&samples).unwrap();
+                    let processed =
+                        write_smoothed(&mut *options.output, &mut smoothers, &samples).unwrap();
+
+                    if let Some(rasterizer) = &mut rasterizer {
+                        rasterizer.push(&processed);
+                    }
                 }
             }
 
-            cur_pos = point.pos;
+            // corner dwell: hold the vertex for a bit proportional to how sharp the
+            // upcoming turn is, so the galvo physically settles before changing direction
+            if let Some(next) = points.get(idx + 1) {
+                let (dot, in_sq, out_sq) = options
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, mc)| mc.is_axis)
+                    .fold((0.0, 0.0, 0.0), |(dot, in_sq, out_sq), (i, _)| {
+                        let incoming = point.pos[i] - cur_pos[i];
+                        let outgoing = next.pos[i] - point.pos[i];
+                        (
+                            dot + incoming * outgoing,
+                            in_sq + incoming * incoming,
+                            out_sq + outgoing * outgoing,
+                        )
+                    });
+
+                if in_sq > 0.0 && out_sq > 0.0 {
+                    let cos_angle = (dot / (in_sq.sqrt() * out_sq.sqrt())).max(-1.0).min(1.0);
+                    let angle = cos_angle.acos();
+
+                    if angle > options.dwell_angle_threshold {
+                        let dwell_samples =
+                            (options.dwell_gain * angle / std::f64::consts::PI).round() as u64;
+                        cur_progress.force_samples(dwell_samples);
+
+                        for _ in 0..dwell_samples {
+                            let processed =
+                                write_smoothed(&mut *options.output, &mut smoothers, &point.pos)
+                                    .unwrap();
+
+                            if let Some(rasterizer) = &mut rasterizer {
+                                rasterizer.push(&processed);
+                            }
+                        }
+                    }
+                }
+            }
+
+            cur_pos = point.pos.clone();
 
             let n = cur_progress.advance(guaranteed_per_sample);
 
             for _ in 1..=n {
-                options.output.write(&cur_pos).unwrap();
+                let processed =
+                    write_smoothed(&mut *options.output, &mut smoothers, &cur_pos).unwrap();
+
+                if let Some(rasterizer) = &mut rasterizer {
+                    rasterizer.push(&processed);
+                }
             }
         }
+
+        if let Some(rasterizer) = &mut rasterizer {
+            rasterizer.finish_frame();
+        }
     }
 
     options.output.finish().unwrap();
+
+    Ok(())
 }
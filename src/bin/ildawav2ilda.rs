@@ -1,22 +1,40 @@
 use byteorder::{LittleEndian, ReadBytesExt};
 use clap::{App, Arg};
-use hound::{Error as HoundError, WavReader};
+use claxon::{Error as ClaxonError, FlacReader};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use hound::{Error as HoundError, SampleFormat, WavReader};
 use ilda::animation::{AnimationStreamWriter, Frame};
 use ilda::{IldaError, SimplePoint};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{self, Error as IoError, ErrorKind, Read, Write};
+use std::io::{self, BufRead, BufReader, Error as IoError, ErrorKind, Read, Write};
 use std::num::{ParseFloatError, ParseIntError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug)]
 enum Error {
     IoError(IoError),
     FailedToInferInputFile,
     UnsupportedBitsPerSample,
+    UnsupportedFormat(String),
+    MarkersRequireWavFile,
     ParseFloatError(ParseFloatError),
     ParseIntError(ParseIntError),
     InvalidChannel(char),
     IldaError(IldaError),
     HoundError(HoundError),
+    ClaxonError(ClaxonError),
+    NoSuchInputDevice(String),
+    NoDefaultInputDevice,
+    FailedToOpenInputStream(String),
+}
+
+impl From<ClaxonError> for Error {
+    fn from(error: ClaxonError) -> Self {
+        Error::ClaxonError(error)
+    }
 }
 
 impl From<ParseFloatError> for Error {
@@ -55,10 +73,148 @@ enum BytesPerSample {
     FourBytes,
 }
 
+// re-opens the input WAV file (hound itself only looks at fmt/data) and walks
+// its RIFF chunk list for a `cue ` chunk, returning each cue point's sample
+// offset into the data chunk, sorted ascending. These become hard frame
+// boundaries instead of --fps accumulated sample time.
+fn read_wav_cue_markers(filename: &str) -> Result<Vec<u32>, Error> {
+    let mut file = BufReader::new(File::open(filename)?);
+
+    let mut riff_header = [0u8; 12];
+    file.read_exact(&mut riff_header)?;
+
+    let mut markers = vec![];
+
+    loop {
+        let mut chunk_id = [0u8; 4];
+        if file.read_exact(&mut chunk_id).is_err() {
+            break;
+        }
+        let chunk_size = file.read_u32::<LittleEndian>()?;
+        let pad = (chunk_size % 2) as u64;
+
+        if &chunk_id == b"cue " {
+            let num_points = file.read_u32::<LittleEndian>()?;
+            for _ in 0..num_points {
+                let mut point = [0u8; 24];
+                file.read_exact(&mut point)?;
+                markers.push(u32::from_le_bytes([point[20], point[21], point[22], point[23]]));
+            }
+            io::copy(&mut (&mut file).take(pad), &mut io::sink())?;
+        } else {
+            io::copy(&mut (&mut file).take(chunk_size as u64 + pad), &mut io::sink())?;
+        }
+    }
+
+    markers.sort_unstable();
+    Ok(markers)
+}
+
+// one raw sample frame, one f64 per mapped channel
+type ChannelSample = Vec<f64>;
+
+// number of taps kept in the sinc interpolation ring buffer
+const SINC_TAPS: usize = 16;
+
+// band-limited sinc resampler that retimes the raw sample stream to the
+// requested galvo point rate, so a --point-rate lower than the source sample
+// rate doesn't just alias by dropping samples
+struct SincResampler {
+    ring: VecDeque<ChannelSample>,
+    pos: f64,
+    ratio: f64,
+    // set once `source` first runs dry, so remaining calls can keep draining
+    // the ring instead of abruptly truncating the output
+    exhausted: bool,
+}
+
+impl SincResampler {
+    fn new(in_rate: u32, target_rate: u32) -> SincResampler {
+        SincResampler {
+            ring: VecDeque::with_capacity(SINC_TAPS),
+            pos: 0.0,
+            ratio: in_rate as f64 / target_rate as f64,
+            exhausted: false,
+        }
+    }
+
+    // pull raw input sample frames from `source` until enough lie in the ring
+    // buffer to evaluate the windowed-sinc kernel at the current fractional
+    // read position. Half the ring holds samples already behind the read
+    // position, half holds samples the read position hasn't reached yet, so
+    // the kernel below is a properly centered (not causal-only) interpolator.
+    fn next<F>(&mut self, mut source: F) -> Option<Result<ChannelSample, Error>>
+    where
+        F: FnMut() -> Option<Result<ChannelSample, Error>>,
+    {
+        let half = SINC_TAPS as f64 / 2.0;
+
+        while !self.exhausted && (self.ring.len() < SINC_TAPS || self.pos >= 1.0) {
+            let sample = match source() {
+                Some(Ok(sample)) => sample,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.exhausted = true;
+                    break;
+                }
+            };
+
+            self.ring.push_back(sample);
+            if self.ring.len() > SINC_TAPS {
+                self.ring.pop_front();
+            }
+            if self.ring.len() == SINC_TAPS {
+                self.pos -= 1.0;
+            }
+        }
+
+        // the read position has moved past every tap that still has nonzero
+        // window weight, or the input ended before the ring ever filled: no
+        // more points can be interpolated
+        if self.ring.len() < SINC_TAPS || (self.exhausted && self.pos >= half) {
+            return None;
+        }
+
+        let channels = self.ring.back().unwrap().len();
+        let mut result = vec![0.0; channels];
+
+        for (i, tap) in self.ring.iter().enumerate() {
+            let offset = (half - 1.0 - i as f64) + self.pos;
+            let weight = sinc_window(offset);
+            for (c, v) in tap.iter().enumerate() {
+                result[c] += v * weight;
+            }
+        }
+
+        self.pos += self.ratio;
+
+        Some(Ok(result))
+    }
+}
+
+// windowed sinc kernel: sinc(x) tapered by a Hann window over +/- SINC_TAPS/2
+fn sinc_window(x: f64) -> f64 {
+    let half = SINC_TAPS as f64 / 2.0;
+    if x.abs() >= half {
+        return 0.0;
+    }
+
+    let sinc = if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+    };
+
+    let window = 0.5 * (1.0 + (std::f64::consts::PI * x / half).cos());
+
+    sinc * window
+}
+
 struct SimplePointRawReader {
     input: Box<dyn Read>,
     bps: BytesPerSample,
     mapping_conf: String,
+    resampler: Option<SincResampler>,
 }
 
 fn to_point(normalized_input: Vec<f64>, mapping_conf: &str) -> Result<SimplePoint, Error> {
@@ -96,69 +252,283 @@ impl Iterator for SimplePointRawReader {
     type Item = Result<SimplePoint, Error>;
 
     fn next(&mut self) -> Option<Result<SimplePoint, Error>> {
-        let mut normalized_input: Vec<_> = vec![];
-
-        for _ in 0..self.mapping_conf.len() {
-            let value = match self.bps {
-                BytesPerSample::OneByte => match self.input.read_i8() {
-                    Ok(data) => data as f64 / i8::max_value() as f64,
-                    Err(e) => match e.kind() {
-                        ErrorKind::UnexpectedEof => return None,
-                        _ => return Some(Err(Error::IoError(e))),
+        let input = &mut self.input;
+        let bps = &self.bps;
+        let channels = self.mapping_conf.len();
+
+        let read_raw = move || {
+            let mut normalized_input: ChannelSample = Vec::with_capacity(channels);
+
+            for _ in 0..channels {
+                let value = match bps {
+                    BytesPerSample::OneByte => match input.read_i8() {
+                        Ok(data) => data as f64 / i8::max_value() as f64,
+                        Err(e) => match e.kind() {
+                            ErrorKind::UnexpectedEof => return None,
+                            _ => return Some(Err(Error::IoError(e))),
+                        },
                     },
-                },
-                BytesPerSample::TwoBytes => match self.input.read_i16::<LittleEndian>() {
-                    Ok(data) => data as f64 / i16::max_value() as f64,
-                    Err(e) => match e.kind() {
-                        ErrorKind::UnexpectedEof => return None,
-                        _ => return Some(Err(Error::IoError(e))),
+                    BytesPerSample::TwoBytes => match input.read_i16::<LittleEndian>() {
+                        Ok(data) => data as f64 / i16::max_value() as f64,
+                        Err(e) => match e.kind() {
+                            ErrorKind::UnexpectedEof => return None,
+                            _ => return Some(Err(Error::IoError(e))),
+                        },
                     },
-                },
-                BytesPerSample::FourBytes => match self.input.read_i32::<LittleEndian>() {
-                    Ok(data) => data as f64 / i32::max_value() as f64,
-                    Err(e) => match e.kind() {
-                        ErrorKind::UnexpectedEof => return None,
-                        _ => return Some(Err(Error::IoError(e))),
+                    BytesPerSample::FourBytes => match input.read_i32::<LittleEndian>() {
+                        Ok(data) => data as f64 / i32::max_value() as f64,
+                        Err(e) => match e.kind() {
+                            ErrorKind::UnexpectedEof => return None,
+                            _ => return Some(Err(Error::IoError(e))),
+                        },
                     },
-                },
-            };
+                };
 
-            normalized_input.push(value);
-        }
+                normalized_input.push(value);
+            }
+
+            Some(Ok(normalized_input))
+        };
 
-        Some(to_point(normalized_input, &self.mapping_conf))
+        let normalized_input = match &mut self.resampler {
+            Some(resampler) => resampler.next(read_raw),
+            None => read_raw(),
+        };
+
+        match normalized_input {
+            Some(Ok(normalized_input)) => Some(to_point(normalized_input, &self.mapping_conf)),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
     }
 }
 
 struct SimplePointHoundReader {
     hound: WavReader<Box<Read>>,
     mapping_conf: String,
+    resampler: Option<SincResampler>,
+}
+
+struct SimplePointFlacReader {
+    flac: FlacReader<Box<dyn Read>>,
+    samples: Vec<i32>,
+    mapping_conf: String,
+    resampler: Option<SincResampler>,
+}
+
+impl Iterator for SimplePointFlacReader {
+    type Item = Result<SimplePoint, Error>;
+
+    fn next(&mut self) -> Option<Result<SimplePoint, Error>> {
+        let flac = &mut self.flac;
+        let buffered = &mut self.samples;
+        let channels = self.mapping_conf.len();
+        let divisor = (1i64 << (flac.streaminfo().bits_per_sample - 1)) as f64 - 1.0;
+
+        let read_raw = move || {
+            while buffered.len() < channels {
+                let mut frame_reader = flac.blocks();
+                match frame_reader.read_next_or_eof(Vec::new()) {
+                    Ok(Some(block)) => {
+                        for i in 0..block.len() {
+                            buffered.push(block.sample(i % channels as u32, i / channels as u32));
+                        }
+                    }
+                    Ok(None) => return None,
+                    Err(e) => return Some(Err(Error::ClaxonError(e))),
+                }
+            }
+
+            let normalized_input: ChannelSample = buffered
+                .drain(0..channels)
+                .map(|s| s as f64 / divisor)
+                .collect();
+
+            Some(Ok(normalized_input))
+        };
+
+        let normalized_input = match &mut self.resampler {
+            Some(resampler) => resampler.next(read_raw),
+            None => read_raw(),
+        };
+
+        match normalized_input {
+            Some(Ok(normalized_input)) => Some(to_point(normalized_input, &self.mapping_conf)),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
 }
 
 impl Iterator for SimplePointHoundReader {
     type Item = Result<SimplePoint, Error>;
 
     fn next(&mut self) -> Option<Result<SimplePoint, Error>> {
-        let mut normalized_input: Vec<_> = vec![];
-
-        for _ in 0..self.mapping_conf.len() {
-            let value = match self.hound.samples::<i32>().next() {
-                Some(Err(e)) => return Some(Err(Error::HoundError(e))),
-                Some(Ok(sample)) => match self.hound.spec().bits_per_sample {
-                    8 => sample as f64 / i8::max_value() as f64,
-                    16 => sample as f64 / i16::max_value() as f64,
-                    32 => sample as f64 / i32::max_value() as f64,
-                    _ => return Some(Err(Error::UnsupportedBitsPerSample)),
+        let hound = &mut self.hound;
+        let channels = self.mapping_conf.len();
+
+        let read_raw = move || {
+            let mut normalized_input: ChannelSample = Vec::with_capacity(channels);
+
+            for _ in 0..channels {
+                let value = match hound.spec().sample_format {
+                    SampleFormat::Float => match hound.samples::<f32>().next() {
+                        Some(Err(e)) => return Some(Err(Error::HoundError(e))),
+                        Some(Ok(sample)) => sample as f64,
+                        None => return None,
+                    },
+                    SampleFormat::Int => match hound.samples::<i32>().next() {
+                        Some(Err(e)) => return Some(Err(Error::HoundError(e))),
+                        Some(Ok(sample)) => match hound.spec().bits_per_sample {
+                            8 => sample as f64 / i8::max_value() as f64,
+                            16 => sample as f64 / i16::max_value() as f64,
+                            24 => sample as f64 / 8_388_607.0,
+                            32 => sample as f64 / i32::max_value() as f64,
+                            _ => return Some(Err(Error::UnsupportedBitsPerSample)),
+                        },
+                        None => return None,
+                    },
+                };
+                normalized_input.push(value);
+            }
+
+            Some(Ok(normalized_input))
+        };
+
+        let normalized_input = match &mut self.resampler {
+            Some(resampler) => resampler.next(read_raw),
+            None => read_raw(),
+        };
+
+        match normalized_input {
+            Some(Ok(normalized_input)) => Some(to_point(normalized_input, &self.mapping_conf)),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
+    }
+}
+
+// captures live audio from an input device into a ring buffer; the audio
+// callback thread fills it in real time while `read_frame` drains it, so the
+// main loop blocks on new samples instead of racing ahead of the hardware
+struct CpalSource {
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    channels: usize,
+    _stream: cpal::Stream,
+}
+
+impl CpalSource {
+    fn new(
+        sample_rate: u32,
+        channels: usize,
+        device_name: &Option<String>,
+    ) -> Result<CpalSource, Error> {
+        let host = cpal::default_host();
+
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()
+                .map_err(|e| Error::NoSuchInputDevice(e.to_string()))?
+                .find(|d| d.name().map(|n| &n == name).unwrap_or(false))
+                .ok_or_else(|| Error::NoSuchInputDevice(name.clone()))?,
+            None => host
+                .default_input_device()
+                .ok_or(Error::NoDefaultInputDevice)?,
+        };
+
+        let config = cpal::StreamConfig {
+            channels: channels as u16,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let callback_buffer = buffer.clone();
+
+        let stream = device
+            .build_input_stream(
+                &config,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    callback_buffer.lock().unwrap().extend(data.iter().copied());
                 },
-                None => return None,
-            };
-            normalized_input.push(value);
+                |err| eprintln!("cpal input stream error: {}", err),
+            )
+            .map_err(|e| Error::FailedToOpenInputStream(e.to_string()))?;
+
+        stream
+            .play()
+            .map_err(|e| Error::FailedToOpenInputStream(e.to_string()))?;
+
+        Ok(CpalSource {
+            buffer,
+            channels,
+            _stream: stream,
+        })
+    }
+
+    // spins until one full sample frame across all channels is buffered, then
+    // returns it as a normalized ChannelSample
+    fn read_frame(&mut self) -> ChannelSample {
+        loop {
+            {
+                let mut buffer = self.buffer.lock().unwrap();
+                if buffer.len() >= self.channels {
+                    return (0..self.channels)
+                        .map(|_| buffer.pop_front().unwrap() as f64)
+                        .collect();
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
         }
+    }
+}
+
+struct SimplePointLiveReader {
+    source: CpalSource,
+    mapping_conf: String,
+    resampler: Option<SincResampler>,
+}
 
-        Some(to_point(normalized_input, &self.mapping_conf))
+impl Iterator for SimplePointLiveReader {
+    type Item = Result<SimplePoint, Error>;
+
+    fn next(&mut self) -> Option<Result<SimplePoint, Error>> {
+        let source = &mut self.source;
+        let read_raw = move || Some(Ok(source.read_frame()));
+
+        let normalized_input = match &mut self.resampler {
+            Some(resampler) => resampler.next(read_raw),
+            None => read_raw(),
+        };
+
+        match normalized_input {
+            Some(Ok(normalized_input)) => Some(to_point(normalized_input, &self.mapping_conf)),
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        }
     }
 }
 
+// best-effort: raise this process' scheduling priority so the live capture
+// loop isn't preempted by other work on the system, which would otherwise show
+// up as stutter in the galvo output. Failure is non-fatal, live mode still
+// works, just without the real-time guarantee (most likely cause: not running
+// as root).
+#[cfg(unix)]
+fn raise_realtime_priority() {
+    unsafe {
+        let param = libc::sched_param {
+            sched_priority: libc::sched_get_priority_max(libc::SCHED_FIFO),
+        };
+        if libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) != 0 {
+            eprintln!("Warning: failed to set real-time scheduling priority (try running as root).");
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn raise_realtime_priority() {}
+
 struct Options {
     input: Box<dyn Read>,
     output: Box<dyn Write>,
@@ -166,14 +536,20 @@ struct Options {
     fps: f64,
     bits_per_sample: u32,
     sample_rate: u32,
+    point_rate: Option<u32>,
     mapping_conf: String,
+    live: bool,
+    device: Option<String>,
+    list_devices: bool,
+    format: Option<String>,
+    markers: Vec<u32>,
 }
 
 fn get_options<'a>() -> Result<Options, Error> {
     let matches = App::new("ildawav2ilda")
         .version("0.1.0")
         .author("Lukas <lukasjapan@gmail.com>")
-        .about("Creates an ilda file from a wav file that contains laser projector control signals. (e.g. files that have been created with the ilda2wav tool.")
+        .about("Creates an ilda file from a wav file that contains laser projector control signals. (e.g. files that have been created with the ilda2wav tool. This is the inverse of ilda2wav: axis samples are mapped back through sample * i16::MAX, color channels through (sample + 1) / 2 * 255, and blanking from the sign of the 'l' channel.")
         .arg(
             Arg::with_name("RAW")
                 .short("r")
@@ -204,6 +580,40 @@ fn get_options<'a>() -> Result<Options, Error> {
                 .help("Bits per sample of raw pcm. This value is ignored unless the input is raw pcm.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("POINTRATE")
+                .long("point-rate")
+                .help("Resamples the input (via windowed-sinc interpolation) to this many points per second before mapping it to galvo coordinates. Use this to hit a target galvo point rate regardless of the source sample rate. If not given, one input sample produces one point.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("FORMAT")
+                .long("format")
+                .help("Input format: wav (default) or flac. Only needed if it cannot be inferred from the input filename's extension or, when reading from STDIN, from the stream's magic bytes. wavpack and tta are recognized by extension/magic bytes but decoding them is not yet implemented, so this tool reports an error rather than reading them.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("LIVE")
+                .short("L")
+                .long("live")
+                .help("Captures audio from a live input device instead of reading a wav/raw file, and writes ILDA frames continuously as it captures. Ignores RAW and the input file; the ILDA output still goes to the given .ild file, or to STDOUT. The CHANNELS string is used as the input device's channel configuration."),
+        )
+        .arg(
+            Arg::with_name("DEVICE")
+                .long("device")
+                .help("Name of the input device to use in live mode. Defaults to the system's default input device.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("MARKERS")
+                .long("markers")
+                .help("Reads the `cue ` chunk from the input WAV file and uses each cue point's sample offset as a hard frame boundary instead of the --fps accumulated sample time. Falls back to --fps for the trailing region after the last marker. Requires a named .wav input file (not STDIN/raw/compressed input)."),
+        )
+        .arg(
+            Arg::with_name("LISTDEVICES")
+                .long("list-devices")
+                .help("Lists available input device names and exits."),
+        )
         .arg(
             Arg::with_name("CHANNELS")
                 .help("A string that defines the channel configuration of the file. Use one or more of the following characters:
@@ -215,7 +625,7 @@ r: Intensity of Red component
 g: Intensity of Green component
 b: Intensity of Blue component
 l: Blanking signal
-_: Ignore 
+_: Ignore
 
 The channel count must match the input file channel count.
 
@@ -223,7 +633,7 @@ Ex:
 A stereo file that controls the axis only: xy
 A 5.1 channel file that controls the axis with rear channels and includes the blanking signal: __l_xy
 ")
-                .required(true)
+                .required_unless("LISTDEVICES")
                 .index(1),
         )
         .arg(
@@ -231,7 +641,8 @@ A 5.1 channel file that controls the axis with rear channels and includes the bl
                 .multiple(true)
                 .help("Specify 0~2 filenames.
 0 filename: Read the input from STDIN and write the output to STDOUT
-1 filename with .wav extension: Read the input from the given file and write the output to STDOUT
+1 filename with .wav or .flac extension: Read the input from the given file and write the output to STDOUT
+1 filename with .wv or .tta extension: Recognized by extension, but decoding is not yet implemented; this tool reports an error
 1 filename with .ild extension: Read the input from STDIN and write the output to the given file
 2 filenames: Read the input from the first file and write the output to the second file")
                 .max_values(2)
@@ -247,6 +658,35 @@ A 5.1 channel file that controls the axis with rear channels and includes the bl
 
     let fps: f64 = matches.value_of("FPS").unwrap().parse()?;
 
+    let point_rate = match matches.value_of("POINTRATE") {
+        Some(v) => Some(v.parse()?),
+        None => None,
+    };
+
+    let live = matches.is_present("LIVE");
+
+    let device = matches.value_of("DEVICE").map(String::from);
+
+    let list_devices = matches.is_present("LISTDEVICES");
+
+    if list_devices {
+        return Ok(Options {
+            input: Box::new(io::stdin()),
+            output: Box::new(io::stdout()),
+            sample_rate,
+            point_rate,
+            raw_pcm,
+            bits_per_sample,
+            mapping_conf: String::new(),
+            fps,
+            live,
+            device,
+            list_devices,
+            format: None,
+            markers: vec![],
+        });
+    }
+
     let files: Vec<&str> = match matches.values_of("FILES") {
         Some(files) => files.collect(),
         None => vec![],
@@ -256,17 +696,55 @@ A 5.1 channel file that controls the axis with rear channels and includes the bl
         1 => match &files[0].to_lowercase()[files[0].len() - 4..] {
             ".wav" => (Some(files[0]), None),
             ".ild" => (None, Some(files[0])),
+            ".tta" => (Some(files[0]), None),
+            _ if files[0].to_lowercase().ends_with(".flac") => (Some(files[0]), None),
+            _ if files[0].to_lowercase().ends_with(".wv") => (Some(files[0]), None),
             _ => return Err(Error::FailedToInferInputFile),
         },
         2 => (Some(files[0]), Some(files[1])),
         _ => (None, None),
     };
 
+    let format_from_name = file_in.and_then(|name| {
+        let name = name.to_lowercase();
+        if name.ends_with(".flac") {
+            Some(String::from("flac"))
+        } else if name.ends_with(".wv") {
+            Some(String::from("wavpack"))
+        } else if name.ends_with(".tta") {
+            Some(String::from("tta"))
+        } else {
+            None
+        }
+    });
+
     let input: Box<dyn Read> = match file_in {
         Some(filename) => Box::new(File::open(filename)?),
         None => Box::new(io::stdin()),
     };
 
+    // when the format wasn't given and couldn't be inferred from a filename
+    // (e.g. piping compressed audio through STDIN), peek the container's
+    // magic bytes without consuming them from the stream
+    let mut input = BufReader::new(input);
+    let format = matches
+        .value_of("FORMAT")
+        .map(String::from)
+        .or(format_from_name)
+        .or_else(|| {
+            let magic = input.fill_buf().ok()?;
+            if magic.starts_with(b"fLaC") {
+                Some(String::from("flac"))
+            } else if magic.starts_with(b"wvpk") {
+                Some(String::from("wavpack"))
+            } else if magic.starts_with(b"TTA1") {
+                Some(String::from("tta"))
+            } else {
+                None
+            }
+        });
+    let input: Box<dyn Read> = Box::new(input);
+
     let output: Box<dyn Write> = match file_out {
         Some(filename) => Box::new(File::create(filename)?),
         None => Box::new(io::stdout()),
@@ -274,18 +752,38 @@ A 5.1 channel file that controls the axis with rear channels and includes the bl
 
     let mapping_conf = matches.value_of("CHANNELS").unwrap().to_string();
 
+    let markers = if matches.is_present("MARKERS") {
+        match file_in {
+            Some(filename) if !raw_pcm && format.as_deref().map_or(true, |f| f == "wav") => {
+                read_wav_cue_markers(filename)?
+            }
+            _ => return Err(Error::MarkersRequireWavFile),
+        }
+    } else {
+        vec![]
+    };
+
     eprintln!("Input:           {}", file_in.unwrap_or("STDIN"));
     eprintln!("Output:          {}", file_out.unwrap_or("STDOUT"));
     eprintln!("Mapping:         {}", mapping_conf);
+    if !markers.is_empty() {
+        eprintln!("Markers:         {} cue points", markers.len());
+    }
 
     Ok(Options {
         input,
         output,
         sample_rate,
+        point_rate,
         raw_pcm,
         bits_per_sample,
         mapping_conf,
         fps,
+        live,
+        device,
+        list_devices,
+        format,
+        markers,
     })
 }
 
@@ -295,7 +793,45 @@ fn main() -> Result<(), Error> {
 
     let options = get_options()?;
 
-    let reader: Box<dyn Iterator<Item = Result<SimplePoint, Error>>> = if options.raw_pcm {
+    if options.list_devices {
+        let host = cpal::default_host();
+        eprintln!("Available input devices:");
+        for device in host
+            .input_devices()
+            .expect("Failed to enumerate input devices.")
+        {
+            eprintln!("  {}", device.name().unwrap_or_else(|_| String::from("<unknown>")));
+        }
+        return Ok(());
+    }
+
+    let point_rate;
+    let mut markers = options.markers.clone();
+
+    let reader: Box<dyn Iterator<Item = Result<SimplePoint, Error>>> = if options.live {
+        eprintln!(
+            "Live capture:    Yes - device {}",
+            options.device.as_deref().unwrap_or("default")
+        );
+
+        raise_realtime_priority();
+
+        point_rate = options.point_rate.unwrap_or(options.sample_rate);
+        let resampler = options
+            .point_rate
+            .filter(|&rate| rate != options.sample_rate)
+            .map(|rate| SincResampler::new(options.sample_rate, rate));
+
+        Box::new(SimplePointLiveReader {
+            source: CpalSource::new(
+                options.sample_rate,
+                options.mapping_conf.len(),
+                &options.device,
+            )?,
+            mapping_conf: options.mapping_conf,
+            resampler,
+        })
+    } else if options.raw_pcm {
         eprintln!(
             "Raw PCM:       Yes - {}bit @ {}Hz",
             options.bits_per_sample, options.sample_rate
@@ -308,37 +844,104 @@ fn main() -> Result<(), Error> {
             _ => return Err(Error::UnsupportedBitsPerSample),
         };
 
+        point_rate = options.point_rate.unwrap_or(options.sample_rate);
+        let resampler = options
+            .point_rate
+            .filter(|&rate| rate != options.sample_rate)
+            .map(|rate| SincResampler::new(options.sample_rate, rate));
+
         Box::new(SimplePointRawReader {
             input: options.input,
             bps,
             mapping_conf: options.mapping_conf,
+            resampler,
         })
-    } else {
+    } else if options.format.as_deref() == Some("flac") {
         eprintln!("Raw PCM:         No");
+        eprintln!("Format:          FLAC");
+
+        let flac = FlacReader::new(options.input)?;
+        let streaminfo = flac.streaminfo();
+
+        point_rate = options.point_rate.unwrap_or(streaminfo.sample_rate);
+        let resampler = options
+            .point_rate
+            .filter(|&rate| rate != streaminfo.sample_rate)
+            .map(|rate| SincResampler::new(streaminfo.sample_rate, rate));
 
-        let reader = Box::new(SimplePointHoundReader {
-            hound: WavReader::new(options.input)?,
+        Box::new(SimplePointFlacReader {
+            flac,
+            samples: vec![],
             mapping_conf: options.mapping_conf,
-        });
+            resampler,
+        })
+    } else if let Some(format) = options.format.filter(|f| f == "wavpack" || f == "tta") {
+        return Err(Error::UnsupportedFormat(format));
+    } else {
+        eprintln!("Raw PCM:         No");
 
-        eprintln!("Sample rate:     {}", reader.hound.spec().sample_rate);
-        eprintln!("Bits per sample: {}", reader.hound.spec().bits_per_sample);
+        let hound = WavReader::new(options.input)?;
+
+        eprintln!("Sample rate:     {}", hound.spec().sample_rate);
+        eprintln!("Bits per sample: {}", hound.spec().bits_per_sample);
+        eprintln!("Sample format:   {:?}", hound.spec().sample_format);
+
+        let source_rate = hound.spec().sample_rate;
+        point_rate = options.point_rate.unwrap_or(source_rate);
+        let resampler = options
+            .point_rate
+            .filter(|&rate| rate != source_rate)
+            .map(|rate| SincResampler::new(source_rate, rate));
+
+        // markers are raw sample offsets in the source file; rescale them into
+        // the resampled/output domain so they still land on the same instant
+        // once --point-rate changes how many output points cover it
+        if !markers.is_empty() && point_rate != source_rate {
+            markers = markers
+                .iter()
+                .map(|&m| (m as u64 * point_rate as u64 / source_rate as u64) as u32)
+                .collect();
+        }
 
-        reader
+        Box::new(SimplePointHoundReader {
+            hound,
+            mapping_conf: options.mapping_conf,
+            resampler,
+        })
     };
 
+    if options.point_rate.is_some() {
+        eprintln!("Point rate:      {}", point_rate);
+    }
+
     let mut writer = AnimationStreamWriter::new(options.output);
 
     let mut current_time = 0.0;
-    let time_per_sample = 1.0 / options.sample_rate as f64;
+    let time_per_sample = 1.0 / point_rate as f64;
     let time_per_frame = 1.0 / options.fps;
     let mut next_frame = time_per_frame;
 
+    let mut marker_index = 0;
+    let mut sample_index: u64 = 0;
+
     let mut points: Vec<SimplePoint> = vec![];
     for result in reader {
         points.push(result?);
+        sample_index += 1;
         current_time = current_time + time_per_sample;
-        if current_time > next_frame {
+
+        if marker_index < markers.len() {
+            if sample_index >= markers[marker_index] as u64 {
+                writer.write_frame(&Frame::new(
+                    points.clone(),
+                    Some(String::from("")),
+                    Some(String::from("")),
+                ))?;
+                points.clear();
+                marker_index += 1;
+                next_frame = current_time + time_per_frame;
+            }
+        } else if current_time > next_frame {
             writer.write_frame(&Frame::new(
                 points.clone(),
                 Some(String::from("")),
@@ -1,17 +1,18 @@
 use chrono::Local;
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 use ilda::SimplePoint;
 use ilda::animation::{Frame, Animation};
 use ilda::writer::IldaWriter;
 use lyon_geom::cubic_bezier::Flattened;
 use lyon_geom::euclid::Point2D;
 use lyon_geom::CubicBezierSegment;
+use std::f64::consts::PI;
 use std::fs::File;
 use std::io::{self, Error as IoError, Read, Write};
-use std::num::ParseFloatError;
+use std::num::{ParseFloatError, ParseIntError};
 use usvg::{
-    Color, Error as UsvgError, Fill, NodeKind, Paint, Path, PathSegment, Stroke, Transform, Tree,
-    Visibility,
+    Color, Error as UsvgError, Fill, NodeKind, Paint, Path, PathSegment, Stop, Stroke, Transform,
+    Tree, Visibility,
 };
 
 struct Point {
@@ -22,12 +23,35 @@ struct Point {
 }
 
 struct Options {
-    input: Box<dyn Read>,
     output: Box<dyn Write>,
     name: String,
     company_name: String,
     invert: bool,
-    tolerance: f64,
+    fps: f64,
+    mode: Mode,
+}
+
+// input geometry either comes from one or more SVG documents, or is generated
+// procedurally by the `shape` subcommand
+enum Mode {
+    Svg {
+        // (frame name hint, reader) pairs: one per input file (or one for STDIN).
+        // A single input whose top-level groups follow the frameN id convention is
+        // still split into multiple frames; see find_frame_groups.
+        inputs: Vec<(String, Box<dyn Read>)>,
+        tolerance: f64,
+    },
+    Polygon {
+        sides: u32,
+        outer_radius: f64,
+        inner_radius: Option<f64>,
+        rotation: f64,
+    },
+    Parametric {
+        x_expr: Expr,
+        y_expr: Expr,
+        samples: u32,
+    },
 }
 
 const DEFAULT_POINT: Point = Point {
@@ -45,10 +69,13 @@ const DEFAULT_POINT: Point = Point {
 enum Error {
     UsvgError(UsvgError),
     ParseFloatError(ParseFloatError),
+    ParseIntError(ParseIntError),
     IoError(IoError),
     FailedToInferInputFile,
     InvalidSvg,
+    InvalidExpression(String),
     SvgTooComplexForIlda,
+    MissingShapeSubcommand,
 }
 
 impl From<UsvgError> for Error {
@@ -63,12 +90,243 @@ impl From<ParseFloatError> for Error {
     }
 }
 
+impl From<ParseIntError> for Error {
+    fn from(error: ParseIntError) -> Self {
+        Error::ParseIntError(error)
+    }
+}
+
 impl From<IoError> for Error {
     fn from(error: IoError) -> Self {
         Error::IoError(error)
     }
 }
 
+// a parametric-curve expression, evaluated at a parameter t in 0..1
+#[derive(Debug, Clone)]
+enum Expr {
+    Const(f64),
+    Var,
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, t: f64) -> f64 {
+        match self {
+            Expr::Const(v) => *v,
+            Expr::Var => t,
+            Expr::Neg(a) => -a.eval(t),
+            Expr::Add(a, b) => a.eval(t) + b.eval(t),
+            Expr::Sub(a, b) => a.eval(t) - b.eval(t),
+            Expr::Mul(a, b) => a.eval(t) * b.eval(t),
+            Expr::Div(a, b) => a.eval(t) / b.eval(t),
+            Expr::Call(name, args) => {
+                let args: Vec<f64> = args.iter().map(|a| a.eval(t)).collect();
+                match (name.as_str(), args.as_slice()) {
+                    ("sin", [x]) => x.sin(),
+                    ("cos", [x]) => x.cos(),
+                    ("sqrt", [x]) => x.sqrt(),
+                    ("pow", [x, y]) => x.powf(*y),
+                    // unreachable: ExprParser only ever constructs Expr::Call with a
+                    // name/arity combination validated in parse_ident_or_call
+                    _ => f64::NAN,
+                }
+            }
+        }
+    }
+}
+
+// small recursive-descent parser for parametric-curve expressions: + - * /,
+// sin/cos/sqrt/pow, the constant pi and the variable t
+struct ExprParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn new(input: &'a str) -> ExprParser<'a> {
+        ExprParser {
+            chars: input.chars().peekable(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<Expr, Error> {
+        let expr = self.parse_expr()?;
+        self.skip_whitespace();
+
+        if self.chars.peek().is_some() {
+            return Err(Error::InvalidExpression(String::from(
+                "Unexpected trailing characters.",
+            )));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_term()?;
+
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('+') => {
+                    self.chars.next();
+                    expr = Expr::Add(Box::new(expr), Box::new(self.parse_term()?));
+                }
+                Some('-') => {
+                    self.chars.next();
+                    expr = Expr::Sub(Box::new(expr), Box::new(self.parse_term()?));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.parse_unary()?;
+
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('*') => {
+                    self.chars.next();
+                    expr = Expr::Mul(Box::new(expr), Box::new(self.parse_unary()?));
+                }
+                Some('/') => {
+                    self.chars.next();
+                    expr = Expr::Div(Box::new(expr), Box::new(self.parse_unary()?));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Error> {
+        self.skip_whitespace();
+
+        if let Some('-') = self.chars.peek() {
+            self.chars.next();
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Error> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('(') => {
+                self.chars.next();
+                let expr = self.parse_expr()?;
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some(')') => Ok(expr),
+                    _ => Err(Error::InvalidExpression(String::from("Expected ')'."))),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || *c == '.' => self.parse_number(),
+            Some(c) if c.is_alphabetic() => self.parse_ident_or_call(),
+            _ => Err(Error::InvalidExpression(String::from(
+                "Expected a number, identifier or '('.",
+            ))),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Expr, Error> {
+        let mut s = String::new();
+
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_digit() || *c == '.' {
+                s.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        s.parse()
+            .map(Expr::Const)
+            .map_err(|_| Error::InvalidExpression(format!("Invalid number: {}", s)))
+    }
+
+    fn parse_ident_or_call(&mut self) -> Result<Expr, Error> {
+        let mut s = String::new();
+
+        while let Some(c) = self.chars.peek() {
+            if c.is_alphanumeric() || *c == '_' {
+                s.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        self.skip_whitespace();
+
+        if let Some('(') = self.chars.peek() {
+            self.chars.next();
+            let mut args = vec![self.parse_expr()?];
+
+            loop {
+                self.skip_whitespace();
+                match self.chars.peek() {
+                    Some(',') => {
+                        self.chars.next();
+                        args.push(self.parse_expr()?);
+                    }
+                    _ => break,
+                }
+            }
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(')') => match (s.as_str(), args.len()) {
+                    ("sin", 1) | ("cos", 1) | ("sqrt", 1) | ("pow", 2) => {
+                        Ok(Expr::Call(s, args))
+                    }
+                    _ => Err(Error::InvalidExpression(format!(
+                        "Unknown function or wrong argument count: {}",
+                        s
+                    ))),
+                },
+                _ => Err(Error::InvalidExpression(String::from("Expected ')'."))),
+            }
+        } else {
+            match s.as_str() {
+                "t" => Ok(Expr::Var),
+                "pi" => Ok(Expr::Const(PI)),
+                _ => Err(Error::InvalidExpression(format!(
+                    "Unknown identifier: {}",
+                    s
+                ))),
+            }
+        }
+    }
+}
+
+fn parse_expr(input: &str) -> Result<Expr, Error> {
+    ExprParser::new(input).parse()
+}
+
 fn get_options<'a>() -> Result<Options, Error> {
     let matches = App::new("svg2ilda")
         .version("0.1.0")
@@ -113,43 +371,94 @@ Please note that the company name in the header can only hold 8 bytes and will b
                 .multiple(true)
                 .required(false)
                 .help(
-                    r#"Specify 0~2 filenames.
+                    r#"Specify 0~2 filenames, or more than 2 to produce a multi-frame animation.
 0 filename: Read the input from STDIN and write the output to STDOUT
 1 filename with .svg extension: Read the input from the given file and write the output to STDOUT
 1 filename with .ild extension: Read the input from STDIN and write the output to the given file
 2 filenames: Read the input from the first file and write the output to the second file
+3+ filenames: Read one SVG frame per file, in order; the last filename may end in .ild to set the output instead of STDOUT
+
+Not used with the shape subcommand, which never reads an SVG; use --output there instead.
                 "#,
                 )
-                .max_values(2)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("FPS")
+                .long("fps")
+                .default_value("20.0")
+                .help("Intended playback rate of the generated animation, used only to report its total duration; the ILDA file itself doesn't store a frame rate.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Output .ild filename. Defaults to STDOUT. This is the only way to set an output file with the shape subcommand."),
+        )
+        .subcommand(
+            SubCommand::with_name("shape")
+                .about("Generates ILDA geometry from polygons or parametric equations, without any SVG input.")
+                .subcommand(
+                    SubCommand::with_name("ngon")
+                        .about("Generates a regular polygon, or a star when --inner-radius is given.")
+                        .arg(
+                            Arg::with_name("SIDES")
+                                .long("sides")
+                                .default_value("5")
+                                .takes_value(true)
+                                .help("Number of sides (or points, for a star)."),
+                        )
+                        .arg(
+                            Arg::with_name("OUTER")
+                                .long("outer-radius")
+                                .default_value("1.0")
+                                .takes_value(true)
+                                .help("Outer radius. The shape is scaled to fill the ILDA coordinate range, so only its ratio to --inner-radius changes the output."),
+                        )
+                        .arg(
+                            Arg::with_name("INNER")
+                                .long("inner-radius")
+                                .takes_value(true)
+                                .help("Inner radius. If given, alternates with the outer radius to produce a star."),
+                        )
+                        .arg(
+                            Arg::with_name("ROTATION")
+                                .long("rotation")
+                                .default_value("0.0")
+                                .takes_value(true)
+                                .help("Rotation of the first vertex, in radians."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("curve")
+                        .about("Generates a parametric curve x(t), y(t) for t in 0..1, e.g. a Lissajous figure.")
+                        .arg(
+                            Arg::with_name("X")
+                                .long("x")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Expression for x(t). Supports + - * /, sin/cos/sqrt/pow, the constant pi and the variable t."),
+                        )
+                        .arg(
+                            Arg::with_name("Y")
+                                .long("y")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Expression for y(t)."),
+                        )
+                        .arg(
+                            Arg::with_name("SAMPLES")
+                                .long("samples")
+                                .default_value("1000")
+                                .takes_value(true)
+                                .help("Number of evenly spaced samples of t."),
+                        ),
+                ),
+        )
         .get_matches();
 
-    let files: Vec<&str> = match matches.values_of("FILES") {
-        Some(files) => files.collect(),
-        None => vec![],
-    };
-
-    let (file_in, file_out) = match files.len() {
-        1 => match &files[0].to_lowercase()[files[0].len() - 4..] {
-            ".ild" => (None, Some(files[0])),
-            ".svg" => (Some(files[0]), None),
-            _ => return Err(Error::FailedToInferInputFile),
-        },
-        2 => (Some(files[0]), Some(files[1])),
-        _ => (None, None),
-    };
-
-    let input: Box<dyn Read> = match file_in {
-        Some(filename) => Box::new(File::open(filename)?),
-        None => Box::new(io::stdin()),
-    };
-
-    let output: Box<dyn Write> = match file_out {
-        Some(filename) => Box::new(File::create(filename)?),
-        None => Box::new(io::stdout()),
-    };
-
     let name = matches
         .value_of("NAME")
         .map_or(format!("s_{}", Local::now().format("%y%m%d")), String::from);
@@ -158,34 +467,377 @@ Please note that the company name in the header can only hold 8 bytes and will b
 
     let invert = matches.is_present("INVERT");
 
-    let tolerance = matches.value_of("TOLERANCE").unwrap().parse()?;
+    let output_flag = matches.value_of("OUTPUT");
+
+    let (mode, output) = if let Some(shape_matches) = matches.subcommand_matches("shape") {
+        let output: Box<dyn Write> = match output_flag {
+            Some(filename) => Box::new(File::create(filename)?),
+            None => Box::new(io::stdout()),
+        };
+
+        let mode = if let Some(ngon) = shape_matches.subcommand_matches("ngon") {
+            Mode::Polygon {
+                sides: ngon.value_of("SIDES").unwrap().parse()?,
+                outer_radius: ngon.value_of("OUTER").unwrap().parse()?,
+                inner_radius: match ngon.value_of("INNER") {
+                    Some(v) => Some(v.parse()?),
+                    None => None,
+                },
+                rotation: ngon.value_of("ROTATION").unwrap().parse()?,
+            }
+        } else if let Some(curve) = shape_matches.subcommand_matches("curve") {
+            Mode::Parametric {
+                x_expr: parse_expr(curve.value_of("X").unwrap())?,
+                y_expr: parse_expr(curve.value_of("Y").unwrap())?,
+                samples: curve.value_of("SAMPLES").unwrap().parse()?,
+            }
+        } else {
+            return Err(Error::MissingShapeSubcommand);
+        };
+
+        eprintln!("Mode:          shape");
+
+        (mode, output)
+    } else {
+        let files: Vec<&str> = match matches.values_of("FILES") {
+            Some(files) => files.collect(),
+            None => vec![],
+        };
+
+        let (svg_files, file_out): (Vec<&str>, Option<&str>) = match files.len() {
+            0 => (vec![], None),
+            1 => match &files[0].to_lowercase()[files[0].len() - 4..] {
+                ".ild" => (vec![], Some(files[0])),
+                ".svg" => (vec![files[0]], None),
+                _ => return Err(Error::FailedToInferInputFile),
+            },
+            2 => (vec![files[0]], Some(files[1])),
+            // 3+ filenames are one SVG frame per file; the last one may be the .ild output
+            _ => match files.split_last() {
+                Some((last, rest)) if last.to_lowercase().ends_with(".ild") => {
+                    (rest.to_vec(), Some(*last))
+                }
+                _ => (files.clone(), None),
+            },
+        };
+
+        let inputs: Vec<(String, Box<dyn Read>)> = if svg_files.is_empty() {
+            vec![(name.clone(), Box::new(io::stdin()))]
+        } else {
+            svg_files
+                .iter()
+                .map(|filename| {
+                    let name = match matches.value_of("NAME") {
+                        Some(name) if svg_files.len() == 1 => name.to_string(),
+                        _ => std::path::Path::new(filename)
+                            .file_stem()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or(filename)
+                            .to_string(),
+                    };
+                    let input: Box<dyn Read> = Box::new(File::open(filename)?);
+                    Ok((name, input))
+                })
+                .collect::<Result<Vec<_>, IoError>>()?
+        };
+
+        let output: Box<dyn Write> = match output_flag.or(file_out) {
+            Some(filename) => Box::new(File::create(filename)?),
+            None => Box::new(io::stdout()),
+        };
+
+        let tolerance = matches.value_of("TOLERANCE").unwrap().parse()?;
+
+        eprintln!("Mode:          svg");
+        eprintln!("Inputs:        {}", inputs.len());
+        eprintln!("Tolerance:     {}", tolerance);
+
+        (Mode::Svg { inputs, tolerance }, output)
+    };
+
+    let fps: f64 = matches.value_of("FPS").unwrap().parse()?;
 
-    eprintln!("Input:         {}", file_in.unwrap_or("STDIN"));
-    eprintln!("Output:        {}", file_in.unwrap_or("STDOUT"));
     eprintln!("Name:          {} / {}", &name[0..8], &company_name[0..8]);
     eprintln!("Invert colors: {}", if invert { "Yes" } else { "No" });
-    eprintln!("Tolerance:     {}", tolerance);
 
     Ok(Options {
-        input,
         output,
         name,
         invert,
         company_name,
-        tolerance,
+        fps,
+        mode,
     })
 }
 
+fn shape_color(invert: bool) -> Color {
+    if invert {
+        Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+        }
+    } else {
+        Color::white()
+    }
+}
+
+// vertices of a regular polygon, or a star when inner_radius is given, closing back to
+// the first vertex
+fn generate_polygon(
+    sides: u32,
+    outer_radius: f64,
+    inner_radius: Option<f64>,
+    rotation: f64,
+    invert: bool,
+) -> Vec<Point> {
+    let color = shape_color(invert);
+    let n = if inner_radius.is_some() { sides * 2 } else { sides };
+
+    (0..=n)
+        .map(|k| {
+            let angle = rotation + 2.0 * PI * k as f64 / n as f64;
+            let radius = match inner_radius {
+                Some(inner) if k % 2 == 1 => inner,
+                _ => outer_radius,
+            };
+
+            Point {
+                x: radius * angle.cos(),
+                y: radius * angle.sin(),
+                color,
+                blank: k == 0,
+            }
+        })
+        .collect()
+}
+
+// samples a parametric curve x(t), y(t) for t evenly spaced in 0..1
+fn generate_parametric(x_expr: &Expr, y_expr: &Expr, samples: u32, invert: bool) -> Vec<Point> {
+    let color = shape_color(invert);
+
+    (0..samples)
+        .map(|i| {
+            let t = if samples > 1 {
+                i as f64 / (samples - 1) as f64
+            } else {
+                0.0
+            };
+
+            Point {
+                x: x_expr.eval(t),
+                y: y_expr.eval(t),
+                color,
+                blank: i == 0,
+            }
+        })
+        .collect()
+}
+
+// builds the matrix that maps a view box (or the bounding box of procedurally
+// generated geometry) to ILDA coordinates
+fn build_transform(x: f64, y: f64, width: f64, height: f64) -> Transform {
+    let dx = -x - width / 2.0;
+    let dy = -y - height / 2.0;
+    let s = i16::max_value() as f64 / width.max(height) * 2.0;
+    let mut t = Transform::default();
+    t.append(&mut Transform::new_scale(s, -s));
+    t.append(&mut Transform::new_translate(dx, dy));
+    t
+}
+
+fn bounding_transform(points: &[Point]) -> Transform {
+    let min_x = points.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+    let max_x = points.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+    let max_y = points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+
+    build_transform(min_x, min_y, max_x - min_x, max_y - min_y)
+}
+
+// finds top-level groups whose id follows the `frameN` convention (frame0, frame1, ...)
+// and returns them in frame order, so a single layered SVG can author a whole
+// animation instead of one static frame
+fn find_frame_groups(root: &usvg::Node) -> Option<Vec<(String, usvg::Node)>> {
+    let mut groups: Vec<(u32, String, usvg::Node)> = vec![];
+
+    for child in root.children() {
+        if let NodeKind::Group(group) = &*child.borrow() {
+            if let Some(index) = group
+                .id
+                .strip_prefix("frame")
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                groups.push((index, group.id.clone(), child.clone()));
+            }
+        }
+    }
+
+    if groups.is_empty() {
+        return None;
+    }
+
+    groups.sort_by_key(|(index, _, _)| *index);
+
+    Some(
+        groups
+            .into_iter()
+            .map(|(_, id, node)| (id, node))
+            .collect(),
+    )
+}
+
+// A path's stroke/fill paint, resolved to whatever we need to compute a color
+// for any point along the flattened path. Gradients are kept as their stops plus
+// the geometry needed to project a point onto them, rather than collapsed up
+// front, so each emitted point can sample its own color.
+enum PointPaint {
+    Solid(Color),
+    Linear {
+        from: (f64, f64),
+        to: (f64, f64),
+        stops: Vec<(f64, Color)>,
+    },
+    Radial {
+        focus: (f64, f64),
+        center: (f64, f64),
+        radius: f64,
+        stops: Vec<(f64, Color)>,
+    },
+}
+
+fn gradient_stops(stops: &[Stop]) -> Vec<(f64, Color)> {
+    stops.iter().map(|stop| (*stop.offset, stop.color)).collect()
+}
+
+// Resolves a `Paint` to a `PointPaint`, following gradient links into the
+// document's defs. `path_transform` is applied to the gradient's own geometry
+// so gradient axis and emitted points end up in the same coordinate space.
+fn resolve_paint(paint: &Paint, tree: &Tree, path_transform: &Transform) -> PointPaint {
+    let link_id = match paint {
+        Paint::Color(color) => return PointPaint::Solid(*color),
+        Paint::Link(id) => id,
+    };
+
+    let gradient_node = match tree.defs_by_id(link_id) {
+        Some(node) => node,
+        None => return PointPaint::Solid(Color::white()),
+    };
+
+    match &*gradient_node.borrow() {
+        NodeKind::LinearGradient(gradient) => {
+            let mut gradient_transform = path_transform.clone();
+            gradient_transform.append(&gradient.base.transform);
+
+            PointPaint::Linear {
+                from: gradient_transform.apply(gradient.x1, gradient.y1),
+                to: gradient_transform.apply(gradient.x2, gradient.y2),
+                stops: gradient_stops(&gradient.base.stops),
+            }
+        }
+        NodeKind::RadialGradient(gradient) => {
+            let mut gradient_transform = path_transform.clone();
+            gradient_transform.append(&gradient.base.transform);
+
+            let center = gradient_transform.apply(gradient.cx, gradient.cy);
+            let edge = gradient_transform.apply(gradient.cx + gradient.r, gradient.cy);
+            let radius = ((edge.0 - center.0).powi(2) + (edge.1 - center.1).powi(2)).sqrt();
+
+            PointPaint::Radial {
+                focus: gradient_transform.apply(gradient.fx, gradient.fy),
+                center,
+                radius,
+                stops: gradient_stops(&gradient.base.stops),
+            }
+        }
+        _ => PointPaint::Solid(Color::white()),
+    }
+}
+
+fn lerp_color(a: Color, b: Color, t: f64) -> Color {
+    Color {
+        red: (a.red as f64 + (b.red as f64 - a.red as f64) * t).round() as u8,
+        green: (a.green as f64 + (b.green as f64 - a.green as f64) * t).round() as u8,
+        blue: (a.blue as f64 + (b.blue as f64 - a.blue as f64) * t).round() as u8,
+    }
+}
+
+// Piecewise-linearly blends between the two stops surrounding `t`.
+fn blend_stops(stops: &[(f64, Color)], t: f64) -> Color {
+    match stops {
+        [] => Color::white(),
+        [(_, color)] => *color,
+        _ => {
+            if t <= stops[0].0 {
+                return stops[0].1;
+            }
+
+            for pair in stops.windows(2) {
+                let (offset_a, color_a) = pair[0];
+                let (offset_b, color_b) = pair[1];
+
+                if t <= offset_b {
+                    let span = offset_b - offset_a;
+                    let local_t = if span > 0.0 { (t - offset_a) / span } else { 0.0 };
+                    return lerp_color(color_a, color_b, local_t);
+                }
+            }
+
+            stops.last().unwrap().1
+        }
+    }
+}
+
+fn sample_paint(paint: &PointPaint, point: (f64, f64)) -> Color {
+    match paint {
+        PointPaint::Solid(color) => *color,
+        PointPaint::Linear { from, to, stops } => {
+            let dx = to.0 - from.0;
+            let dy = to.1 - from.1;
+            let len_sq = dx * dx + dy * dy;
+
+            let u = if len_sq > 0.0 {
+                ((point.0 - from.0) * dx + (point.1 - from.1) * dy) / len_sq
+            } else {
+                0.0
+            };
+
+            blend_stops(stops, u.max(0.0).min(1.0))
+        }
+        PointPaint::Radial {
+            focus,
+            center: _,
+            radius,
+            stops,
+        } => {
+            let dist = ((point.0 - focus.0).powi(2) + (point.1 - focus.1).powi(2)).sqrt();
+            let u = if *radius > 0.0 { dist / *radius } else { 0.0 };
+
+            blend_stops(stops, u.max(0.0).min(1.0))
+        }
+    }
+}
+
+fn invert_color(color: Color) -> Color {
+    Color {
+        red: 255 - color.red,
+        green: 255 - color.green,
+        blue: 255 - color.blue,
+    }
+}
+
 fn collect_points_from_node(
     node: &usvg::Node,
+    tree: &Tree,
     points: &mut Vec<Point>,
     transform: &Transform,
-    options: &Options,
+    invert: bool,
+    tolerance: f64,
 ) {
     match &*node.borrow() {
         NodeKind::Svg(_) => {
             for child in node.children() {
-                collect_points_from_node(&child, points, transform, options);
+                collect_points_from_node(&child, tree, points, transform, invert, tolerance);
             }
         }
         NodeKind::Path(path) => {
@@ -197,37 +849,31 @@ fn collect_points_from_node(
             path_transform.append(&path.transform);
             let path_transform = path_transform;
 
-            let mut color = if let Path {
-                stroke:
-                    Some(Stroke {
-                        paint: Paint::Color(color),
-                        ..
-                    }),
+            let paint = if let Path {
+                stroke: Some(Stroke { paint, .. }),
                 ..
             } = path
             {
-                *color
+                paint
             } else if let Path {
-                fill:
-                    Some(Fill {
-                        paint: Paint::Color(color),
-                        ..
-                    }),
+                fill: Some(Fill { paint, .. }),
                 ..
             } = path
             {
-                *color
+                paint
             } else {
-                Color::white()
+                &Paint::Color(Color::white())
             };
+            let paint = resolve_paint(paint, tree, &path_transform);
 
-            if options.invert {
-                color = Color {
-                    red: 255 - color.red,
-                    green: 255 - color.green,
-                    blue: 255 - color.blue,
+            let color_at = |x: f64, y: f64| -> Color {
+                let color = sample_paint(&paint, (x, y));
+                if invert {
+                    invert_color(color)
+                } else {
+                    color
                 }
-            }
+            };
 
             let mut first_index = points.len();
             for segment in &path.segments {
@@ -237,7 +883,7 @@ fn collect_points_from_node(
                         points.push(Point {
                             x: coord.0,
                             y: coord.1,
-                            color,
+                            color: color_at(coord.0, coord.1),
                             blank: true,
                         })
                     }
@@ -246,7 +892,7 @@ fn collect_points_from_node(
                         points.push(Point {
                             x: coord.0,
                             y: coord.1,
-                            color,
+                            color: color_at(coord.0, coord.1),
                             blank: false,
                         })
                     }
@@ -270,11 +916,11 @@ fn collect_points_from_node(
                             ctrl2: Point2D::new(coord2.0, coord2.1),
                         };
 
-                        for point in Flattened::new(bezier, options.tolerance) {
+                        for point in Flattened::new(bezier, tolerance) {
                             points.push(Point {
                                 x: point.x,
                                 y: point.y,
-                                color,
+                                color: color_at(point.x, point.y),
                                 blank: false,
                             })
                         }
@@ -285,7 +931,7 @@ fn collect_points_from_node(
                         points.push(Point {
                             x: coord.0,
                             y: coord.1,
-                            color,
+                            color: color_at(coord.0, coord.1),
                             blank: false,
                         });
                         first_index = points.len();
@@ -294,7 +940,7 @@ fn collect_points_from_node(
             }
 
             for child in node.children() {
-                collect_points_from_node(&child, points, &path_transform, options);
+                collect_points_from_node(&child, tree, points, &path_transform, invert, tolerance);
             }
         }
         NodeKind::Group(group) => {
@@ -302,82 +948,266 @@ fn collect_points_from_node(
             group_transform.append(&group.transform);
 
             for child in node.children() {
-                collect_points_from_node(&child, points, &group_transform, options);
+                collect_points_from_node(&child, tree, points, &group_transform, invert, tolerance);
             }
         }
         _ => {} // other elements not supported
     }
 }
 
+// Liang-Barsky clipping of the segment from `from` to `to` against the square
+// `[min, max]^2`. Returns the clipped (enter, leave) endpoints, or None if the
+// segment doesn't intersect the square at all.
+fn clip_segment(
+    from: (f64, f64),
+    to: (f64, f64),
+    min: f64,
+    max: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let dx = to.0 - from.0;
+    let dy = to.1 - from.1;
+
+    let mut t_enter = 0.0_f64;
+    let mut t_leave = 1.0_f64;
+
+    let edges = [
+        (-dx, from.0 - min), // left
+        (dx, max - from.0),  // right
+        (-dy, from.1 - min), // bottom
+        (dy, max - from.1),  // top
+    ];
+
+    for (p, q) in edges.iter() {
+        if *p == 0.0 {
+            if *q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if *p < 0.0 {
+                t_enter = t_enter.max(t);
+            } else {
+                t_leave = t_leave.min(t);
+            }
+        }
+    }
+
+    if t_enter > t_leave {
+        return None;
+    }
+
+    let enter = (from.0 + dx * t_enter, from.1 + dy * t_enter);
+    let leave = (from.0 + dx * t_leave, from.1 + dy * t_leave);
+
+    Some((enter, leave))
+}
+
+// Applies `t` to each point and clips lit segments to the guard band instead of
+// dropping whole points, so a stroke that crosses the viewbox edge is cut and
+// resumed rather than snapping to whatever in-bounds point comes next.
+fn clip_points(points: &[Point], t: &Transform) -> Vec<SimplePoint> {
+    let min = i16::min_value() as f64;
+    let max = i16::max_value() as f64;
+
+    let mut cur = (0.0, 0.0);
+    let mut clipped: Vec<SimplePoint> = vec![];
+
+    for point in points {
+        let (x, y) = t.apply(point.x, point.y);
+
+        if point.blank {
+            clipped.push(SimplePoint {
+                x: x.round().max(min).min(max) as i16,
+                y: y.round().max(min).min(max) as i16,
+                r: point.color.red,
+                g: point.color.green,
+                b: point.color.blue,
+                is_blank: true,
+            });
+        } else if let Some((enter, leave)) = clip_segment(cur, (x, y), min, max) {
+            if enter != cur {
+                clipped.push(SimplePoint {
+                    x: enter.0.round() as i16,
+                    y: enter.1.round() as i16,
+                    r: point.color.red,
+                    g: point.color.green,
+                    b: point.color.blue,
+                    is_blank: true,
+                });
+            }
+
+            clipped.push(SimplePoint {
+                x: leave.0.round() as i16,
+                y: leave.1.round() as i16,
+                r: point.color.red,
+                g: point.color.green,
+                b: point.color.blue,
+                is_blank: false,
+            });
+        }
+
+        cur = (x, y);
+    }
+
+    clipped
+}
+
 fn main() -> Result<(), Error> {
     eprintln!("svg2ilda - https://github.com/lukasjapan/ilda-tools");
     eprintln!();
 
     let mut options = get_options()?;
 
-    let mut data: Vec<u8> = vec![];
-    options.input.read_to_end(&mut data)?;
+    let invert = options.invert;
+    let name = options.name.clone();
+    let company_name = options.company_name.clone();
 
-    let tree = Tree::from_data(&data, &usvg::Options::default())?;
-    let root = tree.root();
+    // (frame name, raw points, transform to ILDA coordinates) per frame
+    let frame_data: Vec<(String, Vec<Point>, Transform)> = match &mut options.mode {
+        Mode::Svg { inputs, tolerance } => {
+            if inputs.len() == 1 {
+                let (input_name, input) = &mut inputs[0];
 
-    let view_box = match &*root.borrow() {
-        NodeKind::Svg(svg) => svg.view_box,
-        _ => return Err(Error::InvalidSvg), // This should never happen
-    };
+                let mut data: Vec<u8> = vec![];
+                input.read_to_end(&mut data)?;
 
-    let mut points: Vec<Point> = vec![];
-    collect_points_from_node(&root, &mut points, &Transform::default(), &options);
+                let tree = Tree::from_data(&data, &usvg::Options::default())?;
+                let root = tree.root();
 
-    // Build a matrix that transform to ILDA coordinates
-    let dx = -view_box.rect.x - view_box.rect.width / 2.0;
-    let dy = -view_box.rect.y - view_box.rect.height / 2.0;
-    let s = i16::max_value() as f64 / view_box.rect.width.max(view_box.rect.height) * 2.0;
-    let mut t = Transform::default();
-    t.append(&mut Transform::new_scale(s, -s));
-    t.append(&mut Transform::new_translate(dx, dy));
+                let view_box = match &*root.borrow() {
+                    NodeKind::Svg(svg) => svg.view_box,
+                    _ => return Err(Error::InvalidSvg), // This should never happen
+                };
 
-    // do the actual transformation and filter out values that are outside the viewbox
-    let mut blank_next = false;
-    let points: Vec<_> = points
-        .into_iter()
-        .filter_map(|point| {
-            let (x, y) = t.apply(point.x, point.y);
-            // out of bound
-            if x.round() < i16::min_value() as f64
-                || x.round() > i16::max_value() as f64
-                || y.round() < i16::min_value() as f64
-                || y.round() > i16::max_value() as f64
-            {
-                blank_next = true;
-                None
+                let t = build_transform(
+                    view_box.rect.x,
+                    view_box.rect.y,
+                    view_box.rect.width,
+                    view_box.rect.height,
+                );
+
+                match find_frame_groups(&root) {
+                    Some(groups) => {
+                        eprintln!("Frames:        {} (from frameN groups)", groups.len());
+
+                        groups
+                            .into_iter()
+                            .map(|(id, group)| {
+                                let mut points: Vec<Point> = vec![];
+                                collect_points_from_node(
+                                    &group,
+                                    &tree,
+                                    &mut points,
+                                    &Transform::default(),
+                                    invert,
+                                    *tolerance,
+                                );
+                                (id, points, t.clone())
+                            })
+                            .collect()
+                    }
+                    None => {
+                        let mut points: Vec<Point> = vec![];
+                        collect_points_from_node(
+                            &root,
+                            &tree,
+                            &mut points,
+                            &Transform::default(),
+                            invert,
+                            *tolerance,
+                        );
+                        vec![(input_name.clone(), points, t)]
+                    }
+                }
             } else {
-                Some(SimplePoint {
-                    x: x.round() as i16,
-                    y: y.round() as i16,
-                    r: point.color.red,
-                    g: point.color.green,
-                    b: point.color.green,
-                    is_blank: if blank_next {
-                        blank_next = false;
-                        true
-                    } else {
-                        point.blank
-                    },
-                })
+                eprintln!("Frames:        {} (one per input file)", inputs.len());
+
+                inputs
+                    .iter_mut()
+                    .map(|(input_name, input)| {
+                        let mut data: Vec<u8> = vec![];
+                        input.read_to_end(&mut data)?;
+
+                        let tree = Tree::from_data(&data, &usvg::Options::default())?;
+                        let root = tree.root();
+
+                        let view_box = match &*root.borrow() {
+                            NodeKind::Svg(svg) => svg.view_box,
+                            _ => return Err(Error::InvalidSvg),
+                        };
+
+                        let mut points: Vec<Point> = vec![];
+                        collect_points_from_node(
+                            &root,
+                            &tree,
+                            &mut points,
+                            &Transform::default(),
+                            invert,
+                            *tolerance,
+                        );
+
+                        let t = build_transform(
+                            view_box.rect.x,
+                            view_box.rect.y,
+                            view_box.rect.width,
+                            view_box.rect.height,
+                        );
+
+                        Ok((input_name.clone(), points, t))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?
+            }
+        }
+        Mode::Polygon {
+            sides,
+            outer_radius,
+            inner_radius,
+            rotation,
+        } => {
+            let points = generate_polygon(*sides, *outer_radius, *inner_radius, *rotation, invert);
+            let t = bounding_transform(&points);
+            vec![(name.clone(), points, t)]
+        }
+        Mode::Parametric {
+            x_expr,
+            y_expr,
+            samples,
+        } => {
+            let points = generate_parametric(x_expr, y_expr, *samples, invert);
+            let t = bounding_transform(&points);
+            vec![(name.clone(), points, t)]
+        }
+    };
+
+    let frame_count = frame_data.len();
+
+    let frames: Vec<Frame> = frame_data
+        .into_iter()
+        .map(|(frame_name, points, t)| {
+            let clipped = clip_points(&points, &t);
+
+            if clipped.len() > u16::max_value() as usize {
+                return Err(Error::SvgTooComplexForIlda);
             }
+
+            eprintln!("Frame {}:       {} points", frame_name, clipped.len());
+
+            Ok(Frame::new(
+                clipped,
+                Some(frame_name),
+                Some(company_name.clone()),
+            ))
         })
-        .collect();
+        .collect::<Result<Vec<_>, Error>>()?;
 
-    let len = points.len();
-    if len > u16::max_value() as usize {
-        return Err(Error::SvgTooComplexForIlda);
+    if frame_count > 1 {
+        eprintln!(
+            "Duration:      {:.2}s at {} fps",
+            frame_count as f64 / options.fps,
+            options.fps
+        );
     }
 
-    eprintln!("Points:        {}", len);
-
-    let frame = Frame::new(points, Some(options.name), Some(options.company_name));
-    let frames = vec![frame];
     let animation = Animation::new(frames);
 
     animation.write(options.output);
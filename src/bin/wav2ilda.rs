@@ -1,4 +1,5 @@
 use byteorder::{LittleEndian, ReadBytesExt};
+use claxon::{Error as ClaxonError, FlacReader};
 use clap::{App, Arg};
 use hound::{Error as HoundError, WavReader};
 use ilda::animation::{AnimationStreamWriter, Frame};
@@ -6,6 +7,7 @@ use ilda::{IldaError, SimplePoint};
 use rustfft::algorithm::Radix4;
 use rustfft::num_complex::Complex;
 use rustfft::FFT;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, Error as IoError, ErrorKind, Read, Write};
 use std::num::{ParseFloatError, ParseIntError};
@@ -15,10 +17,22 @@ enum Error {
     IoError(IoError),
     FailedToInferInputFile,
     UnsupportedBitsPerSample,
+    UnknownFilterPreset(String),
+    UnknownWindow(String),
+    UnknownMode(String),
+    UnknownFormat(String),
+    InvalidHop,
     ParseFloatError(ParseFloatError),
     ParseIntError(ParseIntError),
     IldaError(IldaError),
     HoundError(HoundError),
+    ClaxonError(ClaxonError),
+}
+
+impl From<ClaxonError> for Error {
+    fn from(error: ClaxonError) -> Self {
+        Error::ClaxonError(error)
+    }
 }
 
 impl From<ParseFloatError> for Error {
@@ -57,6 +71,110 @@ enum BytesPerSample {
     FourBytes,
 }
 
+// a ring-buffer FIR filter applied to the mono-averaged sample stream before
+// it is handed off to the FFT, e.g. to tame harsh high-frequency content
+struct FirFilter {
+    coeffs: Vec<f64>,
+    state: Vec<f64>,
+    pos: usize,
+}
+
+impl FirFilter {
+    fn new(coeffs: Vec<f64>) -> FirFilter {
+        let len = coeffs.len();
+        FirFilter {
+            coeffs,
+            state: vec![0.0; len],
+            pos: 0,
+        }
+    }
+
+    fn preset(name: &str) -> Result<FirFilter, Error> {
+        let coeffs = match name {
+            "lowpass" => vec![0.1, 0.15, 0.2, 0.3, 0.2, 0.15, 0.1],
+            "highpass" => vec![-0.1, -0.15, -0.2, 0.9, -0.2, -0.15, -0.1],
+            _ => return Err(Error::UnknownFilterPreset(name.to_string())),
+        };
+        Ok(FirFilter::new(coeffs))
+    }
+
+    fn parse(spec: &str) -> Result<FirFilter, Error> {
+        match spec {
+            "lowpass" | "highpass" => FirFilter::preset(spec),
+            _ => {
+                let coeffs: Result<Vec<f64>, _> =
+                    spec.split(',').map(|v| v.trim().parse()).collect();
+                Ok(FirFilter::new(coeffs?))
+            }
+        }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        let len = self.coeffs.len();
+        self.pos = (self.pos + 1) % len;
+        self.state[self.pos] = sample;
+
+        (0..len)
+            .map(|i| self.state[(self.pos + len - i) % len] * self.coeffs[i])
+            .sum()
+    }
+}
+
+enum Window {
+    Rect,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl Window {
+    fn parse(name: &str) -> Result<Window, Error> {
+        match name {
+            "rect" => Ok(Window::Rect),
+            "hann" => Ok(Window::Hann),
+            "hamming" => Ok(Window::Hamming),
+            "blackman" => Ok(Window::Blackman),
+            _ => Err(Error::UnknownWindow(name.to_string())),
+        }
+    }
+
+    // precompute the coefficients for a window of the given length
+    fn coeffs(&self, len: usize) -> Vec<f64> {
+        let n = (len - 1) as f64;
+        (0..len)
+            .map(|i| {
+                let i = i as f64;
+                match self {
+                    Window::Rect => 1.0,
+                    Window::Hann => 0.5 * (1.0 - (2.0 * std::f64::consts::PI * i / n).cos()),
+                    Window::Hamming => {
+                        0.54 - 0.46 * (2.0 * std::f64::consts::PI * i / n).cos()
+                    }
+                    Window::Blackman => {
+                        0.42 - 0.5 * (2.0 * std::f64::consts::PI * i / n).cos()
+                            + 0.08 * (4.0 * std::f64::consts::PI * i / n).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+enum Mode {
+    Bars,
+    Osc,
+}
+
+impl Mode {
+    fn parse(name: &str) -> Result<Mode, Error> {
+        match name {
+            "bars" => Ok(Mode::Bars),
+            "osc" => Ok(Mode::Osc),
+            _ => Err(Error::UnknownMode(name.to_string())),
+        }
+    }
+}
+
 struct Options {
     input: Box<dyn Read>,
     output: Box<dyn Write>,
@@ -65,12 +183,132 @@ struct Options {
     bits_per_sample: u16,
     bins: u16,
     sample_rate: u32,
+    filter: Option<String>,
+    window: String,
+    hop: usize,
+    mode: String,
+    analysis_rate: u32,
+    format: Option<String>,
+    gate: Option<f64>,
+    floor_decay: f64,
+}
+
+// one sample tuple, preserving every input channel (e.g. left/right for osc mode)
+type ChannelSample = Vec<f64>;
+
+// number of taps kept in the sinc interpolation ring buffer
+const SINC_TAPS: usize = 16;
+
+// band-limited sinc resampler that normalizes an arbitrary input sample rate
+// to a fixed internal analysis rate before the FFT, so bin mapping does not
+// shift depending on the source file
+struct SincResampler {
+    ring: VecDeque<ChannelSample>,
+    pos: f64,
+    ratio: f64,
+    // set once `source` first runs dry, so remaining calls can keep draining
+    // the ring instead of abruptly truncating the output
+    exhausted: bool,
+}
+
+impl SincResampler {
+    fn new(in_rate: u32, target_rate: u32) -> SincResampler {
+        SincResampler {
+            ring: VecDeque::with_capacity(SINC_TAPS),
+            pos: 0.0,
+            ratio: in_rate as f64 / target_rate as f64,
+            exhausted: false,
+        }
+    }
+
+    // pull raw input samples from `source` until enough lie in the ring
+    // buffer to evaluate the windowed-sinc kernel at the current fractional
+    // read position. Half the ring holds samples already behind the read
+    // position, half holds samples the read position hasn't reached yet, so
+    // the kernel below is a properly centered (not causal-only) interpolator.
+    fn next<F>(&mut self, mut source: F) -> Option<Result<ChannelSample, Error>>
+    where
+        F: FnMut() -> Option<Result<ChannelSample, Error>>,
+    {
+        let half = SINC_TAPS as f64 / 2.0;
+
+        while !self.exhausted && (self.ring.len() < SINC_TAPS || self.pos >= 1.0) {
+            let sample = match source() {
+                Some(Ok(sample)) => sample,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.exhausted = true;
+                    break;
+                }
+            };
+
+            self.ring.push_back(sample);
+            if self.ring.len() > SINC_TAPS {
+                self.ring.pop_front();
+            }
+            if self.ring.len() == SINC_TAPS {
+                self.pos -= 1.0;
+            }
+        }
+
+        // the read position has moved past every tap that still has nonzero
+        // window weight, or the input ended before the ring ever filled: no
+        // more points can be interpolated
+        if self.ring.len() < SINC_TAPS || (self.exhausted && self.pos >= half) {
+            return None;
+        }
+
+        let channels = self.ring.back().unwrap().len();
+        let mut result = vec![0.0; channels];
+
+        for (i, tap) in self.ring.iter().enumerate() {
+            let offset = (half - 1.0 - i as f64) + self.pos;
+            let weight = sinc_window(offset);
+            for (c, v) in tap.iter().enumerate() {
+                result[c] += v * weight;
+            }
+        }
+
+        self.pos += self.ratio;
+
+        Some(Ok(result))
+    }
+}
+
+// windowed sinc kernel: sinc(x) tapered by a Hann window over +/- SINC_TAPS/2
+fn sinc_window(x: f64) -> f64 {
+    let half = SINC_TAPS as f64 / 2.0;
+    if x.abs() >= half {
+        return 0.0;
+    }
+
+    let sinc = if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+    };
+
+    let window = 0.5 * (1.0 + (std::f64::consts::PI * x / half).cos());
+
+    sinc * window
+}
+
+// a window's worth of samples handed to the visualizers: the raw per-channel
+// rows for the oscilloscope, and the mono-averaged, FIR-filtered rows for the
+// equalizer bars, each filtered exactly once as it enters the window
+struct SampleBlock {
+    channels: Vec<ChannelSample>,
+    bars: Vec<f64>,
 }
 
 struct SamplesHoundReader {
     hound: WavReader<Box<dyn Read>>,
     sample_window: usize,
-    sample_duration: usize,
+    hop: usize,
+    buffer: VecDeque<ChannelSample>,
+    bars: VecDeque<f64>,
+    resampler: Option<SincResampler>,
+    filter: Option<FirFilter>,
 }
 
 struct SamplesRawReader {
@@ -78,15 +316,17 @@ struct SamplesRawReader {
     bps: BytesPerSample,
     channels: u16,
     sample_window: usize,
-    sample_duration: usize,
+    hop: usize,
+    buffer: VecDeque<ChannelSample>,
+    bars: VecDeque<f64>,
+    resampler: Option<SincResampler>,
+    filter: Option<FirFilter>,
 }
 
 impl Iterator for SamplesHoundReader {
-    type Item = Result<Vec<Complex<f64>>, Error>;
-
-    fn next(&mut self) -> Option<Result<Vec<Complex<f64>>, Error>> {
-        let mut result = Vec::with_capacity(self.sample_window);
+    type Item = Result<SampleBlock, Error>;
 
+    fn next(&mut self) -> Option<Result<SampleBlock, Error>> {
         let channels = self.hound.spec().channels as usize;
 
         let divisor = match self.hound.spec().bits_per_sample {
@@ -96,76 +336,238 @@ impl Iterator for SamplesHoundReader {
             _ => return Some(Err(Error::UnsupportedBitsPerSample)),
         };
 
-        // collect samples
-        for _ in 0..self.sample_window {
-            let mut samples = Vec::with_capacity(channels as usize);
-            for _ in 0..channels {
-                let sample = match self.hound.samples::<i32>().next() {
-                    Some(Err(e)) => return Some(Err(Error::HoundError(e))),
-                    Some(Ok(sample)) => sample as f64 / divisor,
-                    None => return None,
-                };
-                samples.push(sample);
+        // on the first call fill the whole window, afterwards only advance by the hop size
+        let to_read = if self.buffer.is_empty() {
+            self.sample_window
+        } else {
+            self.hop
+        };
+
+        for _ in 0..to_read {
+            let hound = &mut self.hound;
+            let read_raw = move || {
+                let mut samples = Vec::with_capacity(channels);
+                for _ in 0..channels {
+                    let sample = match hound.samples::<i32>().next() {
+                        Some(Err(e)) => return Some(Err(Error::HoundError(e))),
+                        Some(Ok(sample)) => sample as f64 / divisor,
+                        None => return None,
+                    };
+                    samples.push(sample);
+                }
+                Some(Ok(samples))
+            };
+
+            let sample = match &mut self.resampler {
+                Some(resampler) => resampler.next(read_raw),
+                None => read_raw(),
+            };
+
+            match sample {
+                Some(Ok(samples)) => {
+                    // average once per newly-ingested sample and run it through the
+                    // FIR filter exactly once, regardless of how many times it is
+                    // re-seen as the sliding window overlaps across hops
+                    let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+                    let avg = match &mut self.filter {
+                        Some(filter) => filter.process(avg),
+                        None => avg,
+                    };
+
+                    self.buffer.push_back(samples);
+                    self.bars.push_back(avg);
+                    if self.buffer.len() > self.sample_window {
+                        self.buffer.pop_front();
+                        self.bars.pop_front();
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
             }
-            let avg = samples.iter().sum::<f64>() / samples.len() as f64;
-            result.push(Complex::new(avg, 0.0));
         }
 
-        // discard the remaining samples
-        for _ in 0..((self.sample_duration - self.sample_window) * channels) {
-            self.hound.samples::<i32>().next();
+        if self.buffer.len() < self.sample_window {
+            return None;
         }
 
-        Some(Ok(result))
+        Some(Ok(SampleBlock {
+            channels: self.buffer.iter().cloned().collect(),
+            bars: self.bars.iter().cloned().collect(),
+        }))
     }
 }
 
 impl Iterator for SamplesRawReader {
-    type Item = Result<Vec<Complex<f64>>, Error>;
-
-    fn next(&mut self) -> Option<Result<Vec<Complex<f64>>, Error>> {
-        let mut result = Vec::with_capacity(self.sample_window);
-
-        // collect samples
-        for _ in 0..self.sample_window {
-            let mut samples = Vec::with_capacity(self.channels as usize);
-            for _ in 0..self.channels {
-                let sample = match self.bps {
-                    BytesPerSample::OneByte => match self.input.read_i8() {
-                        Ok(data) => data as f64 / i8::max_value() as f64,
-                        Err(e) => match e.kind() {
-                            ErrorKind::UnexpectedEof => return None,
-                            _ => return Some(Err(Error::IoError(e))),
+    type Item = Result<SampleBlock, Error>;
+
+    fn next(&mut self) -> Option<Result<SampleBlock, Error>> {
+        let to_read = if self.buffer.is_empty() {
+            self.sample_window
+        } else {
+            self.hop
+        };
+
+        for _ in 0..to_read {
+            let input = &mut self.input;
+            let bps = &self.bps;
+            let channels = self.channels;
+            let read_raw = move || {
+                let mut samples = Vec::with_capacity(channels as usize);
+                for _ in 0..channels {
+                    let sample = match bps {
+                        BytesPerSample::OneByte => match input.read_i8() {
+                            Ok(data) => data as f64 / i8::max_value() as f64,
+                            Err(e) => match e.kind() {
+                                ErrorKind::UnexpectedEof => return None,
+                                _ => return Some(Err(Error::IoError(e))),
+                            },
                         },
-                    },
-                    BytesPerSample::TwoBytes => match self.input.read_i16::<LittleEndian>() {
-                        Ok(data) => data as f64 / i16::max_value() as f64,
-                        Err(e) => match e.kind() {
-                            ErrorKind::UnexpectedEof => return None,
-                            _ => return Some(Err(Error::IoError(e))),
+                        BytesPerSample::TwoBytes => match input.read_i16::<LittleEndian>() {
+                            Ok(data) => data as f64 / i16::max_value() as f64,
+                            Err(e) => match e.kind() {
+                                ErrorKind::UnexpectedEof => return None,
+                                _ => return Some(Err(Error::IoError(e))),
+                            },
                         },
-                    },
-                    BytesPerSample::FourBytes => match self.input.read_i32::<LittleEndian>() {
-                        Ok(data) => data as f64 / i32::max_value() as f64,
-                        Err(e) => match e.kind() {
-                            ErrorKind::UnexpectedEof => return None,
-                            _ => return Some(Err(Error::IoError(e))),
+                        BytesPerSample::FourBytes => match input.read_i32::<LittleEndian>() {
+                            Ok(data) => data as f64 / i32::max_value() as f64,
+                            Err(e) => match e.kind() {
+                                ErrorKind::UnexpectedEof => return None,
+                                _ => return Some(Err(Error::IoError(e))),
+                            },
                         },
-                    },
-                };
-                samples.push(sample);
+                    };
+                    samples.push(sample);
+                }
+                Some(Ok(samples))
+            };
+
+            let sample = match &mut self.resampler {
+                Some(resampler) => resampler.next(read_raw),
+                None => read_raw(),
+            };
+
+            match sample {
+                Some(Ok(samples)) => {
+                    let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+                    let avg = match &mut self.filter {
+                        Some(filter) => filter.process(avg),
+                        None => avg,
+                    };
+
+                    self.buffer.push_back(samples);
+                    self.bars.push_back(avg);
+                    if self.buffer.len() > self.sample_window {
+                        self.buffer.pop_front();
+                        self.bars.pop_front();
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
             }
+        }
 
-            let avg = samples.iter().sum::<f64>() / samples.len() as f64;
-            result.push(Complex::new(avg, 0.0));
+        if self.buffer.len() < self.sample_window {
+            return None;
         }
 
-        // discard the remaining samples
-        //        for _ in 0..((self.sample_duration - self.sample_window) * self.channels) {
-        //            self.hound.samples::<i32>().next()
-        //        }
+        Some(Ok(SampleBlock {
+            channels: self.buffer.iter().cloned().collect(),
+            bars: self.bars.iter().cloned().collect(),
+        }))
+    }
+}
 
-        Some(Ok(result))
+// a source of windowed, hop-advanced sample blocks for the FFT/oscilloscope
+// pipeline, implemented by every supported input container
+trait SampleSource: Iterator<Item = Result<SampleBlock, Error>> {}
+
+impl SampleSource for SamplesHoundReader {}
+impl SampleSource for SamplesRawReader {}
+impl SampleSource for SamplesFlacReader {}
+
+struct SamplesFlacReader {
+    flac: FlacReader<Box<dyn Read>>,
+    samples: Vec<i32>,
+    sample_window: usize,
+    hop: usize,
+    buffer: VecDeque<ChannelSample>,
+    bars: VecDeque<f64>,
+    resampler: Option<SincResampler>,
+    filter: Option<FirFilter>,
+}
+
+impl Iterator for SamplesFlacReader {
+    type Item = Result<SampleBlock, Error>;
+
+    fn next(&mut self) -> Option<Result<SampleBlock, Error>> {
+        let channels = self.flac.streaminfo().channels as usize;
+        let divisor = (1i64 << (self.flac.streaminfo().bits_per_sample - 1)) as f64 - 1.0;
+
+        let to_read = if self.buffer.is_empty() {
+            self.sample_window
+        } else {
+            self.hop
+        };
+
+        for _ in 0..to_read {
+            let flac = &mut self.flac;
+            let buffered = &mut self.samples;
+            let read_raw = move || {
+                while buffered.len() < channels {
+                    let mut frame_reader = flac.blocks();
+                    match frame_reader.read_next_or_eof(Vec::new()) {
+                        Ok(Some(block)) => {
+                            for i in 0..block.len() {
+                                buffered.push(block.sample(i % channels as u32, i / channels as u32));
+                            }
+                        }
+                        Ok(None) => return None,
+                        Err(e) => return Some(Err(Error::ClaxonError(e))),
+                    }
+                }
+
+                let samples: Vec<f64> = buffered
+                    .drain(0..channels)
+                    .map(|s| s as f64 / divisor)
+                    .collect();
+
+                Some(Ok(samples))
+            };
+
+            let sample = match &mut self.resampler {
+                Some(resampler) => resampler.next(read_raw),
+                None => read_raw(),
+            };
+
+            match sample {
+                Some(Ok(samples)) => {
+                    let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+                    let avg = match &mut self.filter {
+                        Some(filter) => filter.process(avg),
+                        None => avg,
+                    };
+
+                    self.buffer.push_back(samples);
+                    self.bars.push_back(avg);
+                    if self.buffer.len() > self.sample_window {
+                        self.buffer.pop_front();
+                        self.bars.pop_front();
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => return None,
+            }
+        }
+
+        if self.buffer.len() < self.sample_window {
+            return None;
+        }
+
+        Some(Ok(SampleBlock {
+            channels: self.buffer.iter().cloned().collect(),
+            bars: self.bars.iter().cloned().collect(),
+        }))
     }
 }
 
@@ -212,12 +614,64 @@ fn get_options<'a>() -> Result<Options, Error> {
                 .help("Amount of equalizer bins for the visualization. Higher values lead to more complex but more detailed output.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("FILTER")
+                .long("filter")
+                .help("FIR filter applied to the signal before the FFT. Either a named preset (lowpass, highpass) or a comma-separated list of coefficients.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("HOP")
+                .long("hop")
+                .help("Number of samples to advance the sliding FFT window between frames. Defaults to the frame's full sample duration. Smaller values overlap windows for smoother visualization.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("WINDOW")
+                .long("window")
+                .default_value("hann")
+                .help("Window function applied to each block before the FFT. One of: hann, hamming, blackman, rect.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ANALYSISRATE")
+                .long("analysis-rate")
+                .default_value("44100")
+                .help("Internal sample rate the input is resampled to (via windowed-sinc interpolation) before the FFT, so the frequency bin mapping is consistent regardless of the source sample rate.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("MODE")
+                .long("mode")
+                .default_value("bars")
+                .help("Visualization mode: bars (equalizer, default) or osc (XY oscilloscope, left channel -> X, right channel -> Y).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("FORMAT")
+                .long("format")
+                .help("Input format: wav (default) or flac. Only needed if it cannot be inferred from the input filename's extension (e.g. when reading from STDIN).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("GATE")
+                .long("gate")
+                .help("Hard noise gate in dB. Bins whose magnitude, after the running noise floor is subtracted, is below this level (20 * log10 of the remaining magnitude) are forced to zero. If not given, no hard gate is applied (only spectral subtraction).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("FLOORDECAY")
+                .long("floor-decay")
+                .default_value("0.1")
+                .help("Smoothing factor (0-1) for the per-bin noise floor estimate. Higher values track the floor faster, at the risk of absorbing quiet signal into the floor.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("FILES")
                 .multiple(true)
                 .help("Specify 0~2 filenames.
 0 filename: Read the input from STDIN and write the output to STDOUT
-1 filename with .wav extension: Read the input from the given file and write the output to STDOUT
+1 filename with .wav or .flac extension: Read the input from the given file and write the output to STDOUT
 1 filename with .ild extension: Read the input from STDIN and write the output to the given file
 2 filenames: Read the input from the first file and write the output to the second file")
                 .max_values(2),
@@ -234,6 +688,32 @@ fn get_options<'a>() -> Result<Options, Error> {
 
     let fps: f64 = matches.value_of("FPS").unwrap().parse()?;
 
+    let filter = matches.value_of("FILTER").map(String::from);
+
+    let window = matches.value_of("WINDOW").unwrap().to_string();
+
+    let mode = matches.value_of("MODE").unwrap().to_string();
+
+    let analysis_rate: u32 = matches.value_of("ANALYSISRATE").unwrap().parse()?;
+
+    // defaults to the frame's full sample duration; a hop of 0 would stall the
+    // sliding window forever, since no new sample is ever pulled from the input
+    let hop = match matches.value_of("HOP") {
+        Some(v) => v.parse()?,
+        None => (analysis_rate as f64 / fps) as usize,
+    };
+
+    if hop < 1 {
+        return Err(Error::InvalidHop);
+    }
+
+    let gate = match matches.value_of("GATE") {
+        Some(v) => Some(v.parse()?),
+        None => None,
+    };
+
+    let floor_decay: f64 = matches.value_of("FLOORDECAY").unwrap().parse()?;
+
     let files: Vec<&str> = match matches.values_of("FILES") {
         Some(files) => files.collect(),
         None => vec![],
@@ -243,12 +723,23 @@ fn get_options<'a>() -> Result<Options, Error> {
         1 => match &files[0].to_lowercase()[files[0].len() - 4..] {
             ".wav" => (Some(files[0]), None),
             ".ild" => (None, Some(files[0])),
+            _ if files[0].to_lowercase().ends_with(".flac") => (Some(files[0]), None),
             _ => return Err(Error::FailedToInferInputFile),
         },
         2 => (Some(files[0]), Some(files[1])),
         _ => (None, None),
     };
 
+    let format = matches.value_of("FORMAT").map(String::from).or_else(|| {
+        file_in.and_then(|name| {
+            if name.to_lowercase().ends_with(".flac") {
+                Some(String::from("flac"))
+            } else {
+                None
+            }
+        })
+    });
+
     let input: Box<dyn Read> = match file_in {
         Some(filename) => Box::new(File::open(filename)?),
         None => Box::new(io::stdin()),
@@ -270,6 +761,14 @@ fn get_options<'a>() -> Result<Options, Error> {
         bits_per_sample,
         bins,
         fps,
+        filter,
+        window,
+        hop,
+        mode,
+        analysis_rate,
+        format,
+        gate,
+        floor_decay,
     })
 }
 
@@ -307,6 +806,77 @@ impl FrequencyWaves {
     }
 }
 
+struct Oscilloscope;
+
+impl Oscilloscope {
+    fn new() -> Oscilloscope {
+        Oscilloscope
+    }
+
+    // draws the laser path directly from time-domain stereo samples:
+    // left channel -> X, right channel -> Y
+    fn samples_to_frame(&mut self, samples: &[ChannelSample]) -> Frame {
+        let points: Vec<_> = samples
+            .iter()
+            .map(|channels| {
+                let left = channels[0];
+                let right = *channels.get(1).unwrap_or(&left);
+                SimplePoint {
+                    x: (left * i16::max_value() as f64) as i16,
+                    y: (right * i16::max_value() as f64) as i16,
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                    is_blank: false,
+                }
+            })
+            .collect();
+
+        Frame::new(points, None, None)
+    }
+}
+
+// tracks a per-bin noise floor and removes it from incoming magnitudes
+// (spectral subtraction), with an optional hard gate on what remains
+struct NoiseGate {
+    floor: Vec<f64>,
+    decay: f64,
+    gate_db: Option<f64>,
+}
+
+impl NoiseGate {
+    fn new(bins: usize, decay: f64, gate_db: Option<f64>) -> NoiseGate {
+        NoiseGate {
+            floor: vec![0.0; bins],
+            decay,
+            gate_db,
+        }
+    }
+
+    fn process(&mut self, bins: Vec<f64>) -> Vec<f64> {
+        let decay = self.decay;
+        let gate_db = self.gate_db;
+
+        bins.into_iter()
+            .zip(self.floor.iter_mut())
+            .map(|(mag, floor)| {
+                if mag < *floor {
+                    *floor = mag;
+                } else {
+                    *floor += decay * (mag - *floor);
+                }
+
+                let subtracted = (mag - *floor).max(0.0);
+
+                match gate_db {
+                    Some(db) if 20.0 * subtracted.log10() < db => 0.0,
+                    _ => subtracted,
+                }
+            })
+            .collect()
+    }
+}
+
 fn get_value(samples: &Vec<Complex<f64>>, from_index: f64, to_index: f64) -> f64 {
     let from_full = from_index.ceil();
     let to_full = to_index.floor();
@@ -336,9 +906,16 @@ fn main() -> Result<(), Error> {
     let mut options = get_options()?;
 
     let sample_window = 256;
-    let sample_duration = (options.sample_rate as f64 / options.fps) as usize;
+    let hop = options.hop;
 
-    let reader: Box<dyn Iterator<Item = Result<Vec<Complex<f64>>, Error>>> = if options.raw_pcm {
+    let filter = match &options.filter {
+        Some(spec) => Some(FirFilter::parse(spec)?),
+        None => None,
+    };
+
+    let mode = Mode::parse(&options.mode)?;
+
+    let reader: Box<dyn SampleSource> = if options.raw_pcm {
         eprintln!("Raw PCM:       Yes");
 
         let bps = match options.bits_per_sample {
@@ -348,12 +925,46 @@ fn main() -> Result<(), Error> {
             _ => return Err(Error::UnsupportedBitsPerSample),
         };
 
+        let resampler = if options.sample_rate != options.analysis_rate {
+            Some(SincResampler::new(options.sample_rate, options.analysis_rate))
+        } else {
+            None
+        };
+
         Box::new(SamplesRawReader {
             input: options.input,
             bps,
             channels: 2,
             sample_window,
-            sample_duration,
+            hop,
+            buffer: VecDeque::with_capacity(sample_window),
+            bars: VecDeque::with_capacity(sample_window),
+            resampler,
+            filter,
+        })
+    } else if options.format.as_deref() == Some("flac") {
+        eprintln!("Raw PCM:         No");
+        eprintln!("Format:          FLAC");
+
+        let flac = FlacReader::new(options.input)?;
+        let streaminfo = flac.streaminfo();
+        options.bits_per_sample = streaminfo.bits_per_sample as u16;
+
+        let resampler = if streaminfo.sample_rate != options.analysis_rate {
+            Some(SincResampler::new(streaminfo.sample_rate, options.analysis_rate))
+        } else {
+            None
+        };
+
+        Box::new(SamplesFlacReader {
+            flac,
+            samples: vec![],
+            sample_window,
+            hop,
+            buffer: VecDeque::with_capacity(sample_window),
+            bars: VecDeque::with_capacity(sample_window),
+            resampler,
+            filter,
         })
     } else {
         eprintln!("Raw PCM:         No");
@@ -365,24 +976,40 @@ fn main() -> Result<(), Error> {
             32 => i32::max_value() as f64,
             _ => return Err(Error::UnsupportedBitsPerSample),
         };
-        let reader = Box::new(SamplesHoundReader {
-            hound,
-            sample_window,
-            sample_duration,
-        });
 
-        options.sample_rate = reader.hound.spec().sample_rate;
-        options.bits_per_sample = reader.hound.spec().bits_per_sample;
+        options.bits_per_sample = hound.spec().bits_per_sample;
+
+        let resampler = if hound.spec().sample_rate != options.analysis_rate {
+            Some(SincResampler::new(
+                hound.spec().sample_rate,
+                options.analysis_rate,
+            ))
+        } else {
+            None
+        };
 
-        reader
+        Box::new(SamplesHoundReader {
+            hound,
+            sample_window,
+            hop,
+            buffer: VecDeque::with_capacity(sample_window),
+            bars: VecDeque::with_capacity(sample_window),
+            resampler,
+            filter,
+        })
     };
 
+    options.sample_rate = options.analysis_rate;
+
     eprintln!("Sample rate:     {}", options.sample_rate);
     eprintln!("Bits per sample: {}", options.bits_per_sample);
 
     let fft = Radix4::new(sample_window, false);
     let mut output = vec![Complex::new(0.0, 0.0); sample_window];
 
+    let window = Window::parse(&options.window)?;
+    let window_coeffs = window.coeffs(sample_window);
+
     let mut writer = AnimationStreamWriter::new(options.output);
 
     // display 20 - 20kHz range
@@ -393,21 +1020,41 @@ fn main() -> Result<(), Error> {
     let log_space_step = (to_index.log2() - log_space_from) / options.bins as f64;
 
     let mut vis = FrequencyWaves::new();
+    let mut osc = Oscilloscope::new();
+    let mut gate = NoiseGate::new(options.bins as usize, options.floor_decay, options.gate);
 
     for result in reader {
-        let mut samples = result?;
-        fft.process(&mut samples, &mut output);
+        let block = result?;
 
-        let bins: Vec<_> = (0..options.bins)
-            .map(|i| {
-                let from_index = (2.0 as f64).powf(log_space_from + i as f64 * log_space_step);
-                let to_index =
-                    (2.0 as f64).powf(log_space_from + (i as f64 + 1.0) * log_space_step);
-                get_value(&samples, from_index, to_index)
-            })
-            .collect();
+        let frame = match mode {
+            Mode::Osc => osc.samples_to_frame(&block.channels),
+            Mode::Bars => {
+                let mut samples: Vec<_> =
+                    block.bars.iter().map(|avg| Complex::new(*avg, 0.0)).collect();
+
+                for (sample, w) in samples.iter_mut().zip(window_coeffs.iter()) {
+                    sample.re *= w;
+                }
+
+                fft.process(&mut samples, &mut output);
+
+                let bins: Vec<_> = (0..options.bins)
+                    .map(|i| {
+                        let from_index =
+                            (2.0 as f64).powf(log_space_from + i as f64 * log_space_step);
+                        let to_index = (2.0 as f64)
+                            .powf(log_space_from + (i as f64 + 1.0) * log_space_step);
+                        get_value(&samples, from_index, to_index)
+                    })
+                    .collect();
+
+                let bins = gate.process(bins);
+
+                vis.bins_to_frame(bins)
+            }
+        };
 
-        writer.write_frame(&vis.bins_to_frame(bins))?;
+        writer.write_frame(&frame)?;
     }
 
     writer.finalize()?;